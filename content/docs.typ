@@ -26,9 +26,49 @@ author = \"\"
 base_url = \"https://example.com\"
 description = \"optional field\"
 
+[build]
+git_dates = false
+check_links = false
+hashed_filenames = false
+extract_frames = false
+ignore = []
+
+[typst]
+math = \"typst\"
+
 [[links]]
 slug = \"name\"
 path = \"path/to/content/dir\"
+override = false
+```
+
+Two source files that end up at the same path, like a page in a link shadowing one in `content`,
+fail the build, naming both. Set `override = true` on a link to let its files replace the others.
+
+Links take a few more options:
+
+/ `include`, `exclude`: Globs relative to the link's `path`. Only files matching `include`, when
+  given, and not matching `exclude` are rendered and copied
+/ `menu`, `feeds`: Set to `false` to leave the link's section out of `menu` or the RSS feed. Its
+  pages are still built and found by `get_page`
+/ `templates`: A directory of templates for the link's pages. Templates it does not define, like a
+  shared `base.html`, come from `templates`. When it lives inside the link's `path` it is neither
+  rendered nor copied to `dist`
+/ `title`: Title of the link's section, instead of the title of its `index.typ`
+/ `root`: `"project"` or `"mount"`. With `"mount"`, absolute paths in the link's typst files, like
+  `#import "/lib.typ"`, resolve from the link's own directory
+
+```toml
+[[links]]
+slug = "notes"
+path = "../notes"
+include = ["**/*.typ", "**/*.png"]
+exclude = ["drafts/**"]
+menu = true
+feeds = false
+templates = "../notes/templates"
+title = "Notes"
+root = "mount"
 ```
 
 = Templates
@@ -44,6 +84,97 @@ Didactic uses #link("https://keats.github.io/tera/")[Tera] for templating.
   `children`. Children are only used on index pages.
 / `content`: Rendered content
 / `current_section`: Current directory name
+/ `page`: The current page, with the same fields as a menu item plus `date` and `updated`
+/ `page.toc`: Nested table of contents for the page. Each entry exposes `level`, `text`, `id`, and
+  `children`. Every heading in `content` gets a unique `id` to link to.
+
+== Template Functions
+/ `asset_url(path=...)`: Cache busted url of an asset, like `asset_url(path="/style.css")`
+/ `url_for(page=...)`: Url of a page from its logical path, like `url_for(page="notes/intro.typ")`
+/ `get_page(path=...)`: A page by its logical path, with the same fields as a menu item
+/ `get_section(path=...)`: A section by its directory, with its pages in `children`
+
+All of these fail the build when the asset or page does not exist. The urls from `asset_url` and
+`url_for` are not escaped, so they can go straight into an attribute without `| safe`. With
+`hashed_filenames`, assets that are never renamed, like `favicon.ico`, keep their path.
+
+== Assets
+
+Every local asset referenced from a page gets a `?v=<hash>` query appended so browsers refetch it when
+it changes. This covers `href`, `src`, `srcset`, `poster`, `data` and `url(...)` or `@import` in
+styles, including the compiled `style.css`. References that already have a query string are left
+alone.
+
+Some CDNs and proxies ignore query strings, so with `hashed_filenames` set under `[build]` assets
+are renamed to include their hash instead, like `style.3fa9c1ab.css`, and references are rewritten to
+match. The mapping from original to renamed urls is written to `dist/asset-manifest.json`.
+
+Everything in `content`, `static` and linked directories is copied to `dist` apart from `.typ`,
+`.toml` and `.scss` files. Dotfiles are skipped, except `.well-known`. More files can be left out
+with a `.didacticignore` file in the project root, using gitignore syntax, or the same patterns in
+`ignore` under `[build]`. Ignored `.typ` files are not rendered either. Whitelist patterns bring
+files back, including dotfiles like `.htaccess` or `.nojekyll`.
+
+```sh
+*.psd
+*.swp
+drafts/
+!.htaccess
+```
+
+== Styles
+
+By default `templates/main.scss` is compiled to `dist/style.css`. A `[styles]` table lists other
+entry points instead, with `output` relative to `dist`. `load_paths` are searched by `@use` and
+`@import`, for example to share partials from a linked directory, and `style` is either `"expanded"`
+or `"compressed"`. A listed entry that does not exist fails the build, while a missing default
+`templates/main.scss` is skipped.
+
+```toml
+[styles]
+entries = [
+  { input = "templates/main.scss", output = "style.css" },
+  { input = "templates/print.scss", output = "css/print.css" },
+]
+load_paths = ["notes/styles"]
+style = "compressed"
+```
+
+Source maps are not supported, since #link("https://github.com/connorskees/grass")[grass] does not
+generate them.
+
+`didactic build --minify` minifies every page along with its inline `<style>` and `<script>`, and
+every `.css` and `.js` file in `dist`. Files named like `vendor.min.js` are taken to be minified
+already and left alone.
+
+The `[minify]` table tunes the minifier and lists the build profiles that always minify, so
+`didactic build` minifies while `didactic build -p dev` does not. Pages and assets matching an
+`exclude` glob, relative to `dist`, are never minified.
+
+```toml
+[minify]
+profiles = ["release"]
+keep_comments = false
+keep_closing_tags = false
+keep_ssi_comments = false
+minify_css = true
+minify_js = true
+exclude = ["vendor/**", "legacy.html"]
+```
+
+With a `[compress]` table, every HTML, CSS, JS, XML, SVG and JSON file of at least `min_size` bytes
+gets precompressed `.gz` and `.br` siblings as the last step of the build, for hosts that serve them
+directly. Encodings that do not shrink a file are skipped. The siblings written are recorded in
+`.didactic-cache`, so those left by an earlier build are removed once their encoding is turned off
+or the table is removed. A `.gz` or `.br` file the build did not write, like one shipped in
+`static`, is never overwritten or removed.
+
+```toml
+[compress]
+gzip = true
+brotli = true
+min_size = 1024
+```
 
 = Typst
 
@@ -53,16 +184,149 @@ page at the same relative path.
 == Document Metadata
 
 Every page needs to declare a title and optionally a date. Pages without a date are not included in
-RSS.
+the RSS and Atom feeds, `rss.xml` and `atom.xml`. Every page is listed in `sitemap.xml`.
 
 ```typst
 #set document(title: \"\", date: datetime(year: 1970, month: 1, day: 1))
 ```
 
+With `git_dates` enabled under `[build]`, each page also gets an `updated` date from the last git
+commit that touched its source file. Outside of a git repository the file modification time is used
+instead. The same date is used for `lastmod` in the sitemap and `<updated>` in the Atom feed.
+
+== Inputs
+
+Every page gets these values through `sys.inputs`:
+
+/ `target`: Always `"html"`
+/ `site`: The `[site]` table, with `title`, `author`, `base_url` and `description`
+/ `profile`: The build profile passed with `-p`, `"release"` by default
+/ `path`: The page's logical path, like `notes/intro.typ`
+/ `url`: The url the page is linked under, like `/notes/intro.html`
+/ `labels`: Cross page labels, see below
+
+Anything under `[typst.inputs]` is passed along as well, keeping its TOML type. It can not replace
+the values above.
+
+```toml
+[typst.inputs]
+analytics = false
+authors = ["Akari"]
+```
+
+```typst
+#let site = sys.inputs.at("site", default: (base_url: ""))
+#link(site.base_url + sys.inputs.at("url", default: "/"))[Permalink]
+```
+
+== Prelude and Libraries
+
+Shared typst files that pages import, but that are not pages themselves, are listed under
+`libraries` so they are not rendered. A file called `lib.typ` that is not listed is rendered like
+any other page, with a warning.
+
+Every page imports the definitions of the `prelude` file without an import of its own. Show and set
+rules only apply inside the file that declares them, so put the ones pages should share in a
+function called `rules`, which is applied to every page. The prelude is never rendered on its own,
+and its content outside definitions is not shown. Imports in the prelude should use absolute paths
+like `/content/lib.typ`.
+
+```toml
+[typst]
+libraries = ["content/lib.typ"]
+prelude = "content/prelude.typ"
+```
+
+```typst
+#let note(body) = block(inset: 8pt, stroke: gray, body)
+#let rules(body) = {
+  show math.equation: it => html.frame(it)
+  body
+}
+```
+
+== Fonts
+
+Typst only knows its bundled fonts unless told otherwise, so frames fall back to them. Directories
+listed in `fonts` under `[typst]`, or passed with `--font-path`, are searched for `.ttf`, `.otf`,
+`.ttc` and `.otc` files that pages can then use.
+
+With `web_fonts` set, every single face font from those directories is copied to `dist/fonts/`, along
+with a `.woff2` of the same name when there is one, and `dist/fonts.css` gets an `@font-face` rule
+for each. Link it from your template to use the same fonts in HTML. Fonts are copied whether or not
+any page uses them, so keep only the faces the site needs in those directories. Font collections
+are skipped, and without any single face font no `fonts.css` is written.
+
+```toml
+[typst]
+fonts = ["fonts"]
+web_fonts = true
+```
+
+== Packages
+
+Packages like `@preview/cetz:0.4.2` and `@local/notes:0.1.0` are read from disk, never downloaded.
+Didactic looks for them in the directory set by `packages` under `[typst]`, then in typst's local
+package directory and finally in its package cache, each laid out as
+`<namespace>/<name>/<version>`. On Linux those are `~/.local/share/typst/packages` and
+`~/.cache/typst/packages`, so anything the typst CLI has downloaded before is found.
+
+To build without relying on the machine's cache, copy the packages into the project:
+
+```toml
+[typst]
+packages = "vendor/packages"
+```
+
+== Links
+
+Links to other `.typ` files are rewritten to the url of the rendered page. Relative paths are
+resolved from the linking page, absolute paths from the content root, which includes the slugs of
+any `[[links]]`. Linking to a file that does not exist fails the build.
+
+`didactic check` goes through every page in `dist` and reports internal links, `srcset` candidates
+and anchors that do not resolve, along with the `.typ` file they came from. It exits with a non-zero
+status when anything is broken, so it can gate a CI job. Set `check_links` under `[build]` to run it
+after every build, which fails the build the same way.
+
+```typst
+#link("notes/intro.typ")[Introduction]
+#link("/index.typ#quickstart")[Quickstart]
+```
+
+== Cross Page References
+
+Typst references only see labels in the same document, so Didactic collects every labelled heading
+across the site and passes them back in as `sys.inputs.labels`. Each label maps to the `url` of the
+heading and its `text`. When there are any, every page gets a `show ref` rule that turns a
+reference to a label on another page into a link to it, so `@intro-chapter` links to the heading
+labelled `<intro-chapter>` wherever it is. References to labels in the same page are left to Typst.
+
+Labels are read from the page sources before anything is compiled. Only headings written as
+`= Heading <label>` are collected, and each of them gets its label as `id` so the link lands on it.
+
+== Responsive Images
+
+Adding an `[images]` table to `didactic.toml` resizes every jpg, png and webp image to the listed
+widths and converts it to `webp`, `avif` or keeps the `original` format. Images are never scaled up,
+and re-encoding strips EXIF data. `<img>` tags pointing at a processed image get a `srcset`, the
+configured `sizes`, and their intrinsic `width` and `height` unless one is already set. Resized
+images are cached in `.didactic-cache` so unchanged images are not processed again.
+
+```toml
+[images]
+widths = [480, 960, 1920]
+format = "webp"
+sizes = "(max-width: 800px) 100vw, 800px"
+```
+
+Every `<img>` pointing at a local image also gets its intrinsic `width` and `height`, read from the
+file, along with `loading="lazy"` and `decoding="async"`. Attributes set by hand are kept.
+
 == Math
 
 Math has to be rendered using `html.frame`, which produces inline SVGs. This can be done
-automatically by putting this at the top of your document:
+automatically by putting this at the top of your document, or once in the prelude's `rules`:
 
 ```typst
 #show math.equation: it => html.frame(it)
@@ -77,6 +341,63 @@ Becomes:
 #show math.equation: it => html.frame(it)
 $ x = (-b plus.minus sqrt(b^2 - 4a c)) / (2a) $
 
+Every frame inlines a full SVG, glyph outlines included. With `extract_frames` set under `[build]`,
+each frame is written to `dist/frames/<hash>.svg` instead and referenced with an `<img>`, using the
+source of the equation it renders as alt text. Identical frames across the site share a single file,
+and frames no longer used are removed on the next build.
+
+Frames are drawn in black, which disappears on a dark background. Set `frame_colors` under `[build]`
+to rewrite their colors. With `"currentcolor"` black fills and strokes become `currentColor`, so math
+follows the text color. With `"variables"` every color is read from a CSS variable named after it,
+like `--frame-ff0000`, falling back to the original color, and black falling back to `currentColor`.
+Extracted frames are loaded as images and can not inherit colors from the page.
+
+```css
+@media (prefers-color-scheme: dark) {
+  :root { --frame-ff0000: #ff8080; }
+}
+```
+
+Setting `math = "mathml"` under `[typst]` converts equations to MathML instead, which browsers
+render natively as selectable text. Fractions, roots, sub and superscripts, delimiters and plain
+symbols are supported. An equation using anything else, like a matrix, falls back to `html.frame` as
+a whole. Equations already wrapped in `html.frame` by your own show rule are left alone.
+
+```toml
+[typst]
+math = "mathml"
+```
+
+== Syntax Highlighting
+
+With a `[highlight]` table, code blocks with a language get class based token spans instead of the
+inline colors typst exports, so they can be themed from CSS. Any syntax and theme bundled with
+#link("https://github.com/trishume/syntect")[syntect] can be used. Setting `stylesheet` writes the
+token colors to `dist/highlight.css`, with `dark_theme` behind a `prefers-color-scheme` media query.
+Link it from your template like any other stylesheet.
+
+```toml
+[highlight]
+theme = "InspiredGitHub"
+dark_theme = "base16-ocean.dark"
+stylesheet = true
+```
+
+Line numbers and highlighted lines are set per block with the bundled `code` function. Lines to
+highlight are numbers, or `(start, end)` pairs for ranges.
+
+````typst
+#import "/@didactic/code.typ": code
+
+#code(numbers: true, highlight: (2,), ```rust
+fn main() {
+    println!("Hello");
+}
+```)
+````
+
+It also works as a show rule, like `#show raw.where(block: true): code.with(numbers: true)`.
+
 == Images
 
 Typst will by default include images as a base64 blob inline. Didactic does not detect this
@@ -119,3 +440,5 @@ like so:
 ```
 Also of note, Didactic passes the input `target` as `html` to the typst compiler. Typst's builtin
 `#target()` requires context and ive found it to give unexpected results.
+
+Rather than pasting helpers like this into every page, put them in a prelude.
@@ -62,7 +62,11 @@ python -m http.server --directory dist
 / `didactic help`: Show help text
 / `didactic build`: Builds a site
   / `-m`: Minify the output
+  / `-p`: The build profile, used by `[minify] profiles` [default: `release`]
+  / `--font-path`: An extra directory of fonts, can be given more than once
   / `-d`: The root directory to build [default: `./`]
+/ `didactic check`: Checks the built site for broken internal links and missing anchors
+  / `-d`: The root directory of the build to check [default: `./`]
 / `didactic clean`: Clean the build directory byt deleting the `dist` folder
   / `-d`: The root directory of the build to clean [default: `./`]
 
@@ -0,0 +1,315 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self};
+use std::path::Path;
+
+use lol_html::html_content::ContentType;
+use lol_html::{RewriteStrSettings, element, rewrite_str, text};
+use regex::{Captures, Regex};
+
+use crate::build::hash_bytes;
+use crate::links::normalize;
+
+pub const MANIFEST_NAME: &str = "asset-manifest.json";
+
+/// Attributes holding a single url that may point at an asset.
+const URL_ATTRS: [&str; 5] = ["href", "src", "poster", "data", "xlink:href"];
+
+/// Assets with these extensions get fingerprinted filenames. Anything a browser requests by a fixed
+/// name, like `favicon.ico` or `robots.txt`, is left out.
+const FINGERPRINTED: [&str; 15] = [
+    "css", "js", "mjs", "png", "jpg", "jpeg", "gif", "webp", "avif", "svg", "woff", "woff2", "ttf",
+    "otf", "mp4"
+];
+
+/// Turns references to local assets into cache busted urls, either by appending the content hash
+/// as a query or by pointing at the fingerprinted filename.
+pub enum AssetUrls<'a> {
+    Query(&'a HashMap<String, String>),
+    Renamed(&'a BTreeMap<String, String>)
+}
+
+impl AssetUrls<'_> {
+    /// Busts `url` as referenced from the document at `base`, which is an absolute url path such
+    /// as `/notes/index.html`. Returns `None` for external urls and unknown assets.
+    pub fn bust(&self, url: &str, base: &str) -> Option<String> {
+        if url.is_empty() || url.contains('?') || is_external(url) {
+            return None;
+        }
+        let (path, fragment) = match url.split_once('#') {
+            Some((path, fragment)) => (path, format!("#{fragment}")),
+            None => (url, String::new())
+        };
+        let key = resolve(path, base)?;
+        match self {
+            AssetUrls::Query(hashes) => {
+                let hash = hashes.get(&key)?;
+                Some(format!("{path}?v={hash}{fragment}"))
+            }
+            AssetUrls::Renamed(manifest) => {
+                let renamed = manifest.get(&key)?;
+                Some(format!("{renamed}{fragment}"))
+            }
+        }
+    }
+
+    fn bust_or_keep(&self, url: &str, base: &str) -> String {
+        self.bust(url, base).unwrap_or_else(|| url.to_string())
+    }
+
+    fn bust_srcset(&self, srcset: &str, base: &str) -> String {
+        srcset
+            .split(',')
+            .map(|candidate| {
+                let candidate = candidate.trim();
+                match candidate.split_once(char::is_whitespace) {
+                    Some((url, descriptor)) => {
+                        format!("{} {}", self.bust_or_keep(url, base), descriptor.trim())
+                    }
+                    None => self.bust_or_keep(candidate, base)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Busts every local asset reference in a rendered page: the url attributes in `URL_ATTRS`,
+/// `srcset`, and `url(...)` or `@import` in `<style>` blocks and `style` attributes.
+pub fn bust_html(html: &str, base: &str, assets: &AssetUrls) -> Result<String, Box<dyn Error>> {
+    let mut style = String::new();
+    Ok(rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("*", |el| {
+                    for name in URL_ATTRS {
+                        if let Some(busted) = el
+                            .get_attribute(name)
+                            .and_then(|url| assets.bust(&url, base))
+                        {
+                            el.set_attribute(name, &busted)?;
+                        }
+                    }
+                    if let Some(srcset) = el.get_attribute("srcset") {
+                        el.set_attribute("srcset", &assets.bust_srcset(&srcset, base))?;
+                    }
+                    if let Some(css) = el.get_attribute("style") {
+                        el.set_attribute("style", &bust_css(&css, base, assets))?;
+                    }
+                    Ok(())
+                }),
+                text!("style", |chunk| {
+                    style.push_str(chunk.as_str());
+                    if chunk.last_in_text_node() {
+                        chunk.replace(&bust_css(&style, base, assets), ContentType::Html);
+                        style.clear();
+                    } else {
+                        chunk.remove();
+                    }
+                    Ok(())
+                })
+            ],
+            ..RewriteStrSettings::new()
+        }
+    )?)
+}
+
+/// Busts `url(...)` and `@import` references in a stylesheet served from `base`.
+pub fn bust_css(css: &str, base: &str, assets: &AssetUrls) -> String {
+    css_url_re()
+        .replace_all(css, |caps: &Captures| {
+            let Some((url, import)) = css_url(caps) else {
+                return caps[0].to_string();
+            };
+            match assets.bust(url, base) {
+                Some(busted) if import => format!(r#"@import "{busted}""#),
+                Some(busted) => format!(r#"url("{busted}")"#),
+                None => caps[0].to_string()
+            }
+        })
+        .to_string()
+}
+
+fn css_url_re() -> Regex {
+    Regex::new(
+        r#"url\(\s*(?:"([^"]*)"|'([^']*)'|([^)'"\s]+))\s*\)|@import\s+(?:"([^"]*)"|'([^']*)')"#
+    )
+    .unwrap()
+}
+
+/// The url of a `css_url_re` match and whether it is a bare `@import`.
+fn css_url<'a>(caps: &Captures<'a>) -> Option<(&'a str, bool)> {
+    let url = caps.get(1).or_else(|| caps.get(2)).or_else(|| caps.get(3));
+    url.map(|u| (u.as_str(), false)).or_else(|| {
+        caps.get(4)
+            .or_else(|| caps.get(5))
+            .map(|u| (u.as_str(), true))
+    })
+}
+
+/// Orders `stylesheets` so each one comes after the stylesheets it references, which must be
+/// busted or renamed first for the reference to point at their final content.
+fn dependency_order(out_dir: &Path, stylesheets: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+    fn visit(
+        url: &String,
+        deps: &HashMap<&String, Vec<String>>,
+        seen: &mut HashSet<String>,
+        order: &mut Vec<String>
+    ) {
+        if !seen.insert(url.clone()) {
+            return;
+        }
+        for dep in &deps[url] {
+            visit(dep, deps, seen, order);
+        }
+        order.push(url.clone());
+    }
+
+    let re = css_url_re();
+    let mut deps = HashMap::new();
+    for url in stylesheets {
+        let css = fs::read_to_string(out_dir.join(url.trim_start_matches('/')))?;
+        let referenced = re
+            .captures_iter(&css)
+            .filter_map(|caps| resolve(css_url(&caps)?.0, url))
+            .filter(|dep| dep != url && stylesheets.contains(dep))
+            .collect();
+        deps.insert(url, referenced);
+    }
+
+    let mut sorted: Vec<&String> = stylesheets.iter().collect();
+    sorted.sort();
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    for url in sorted {
+        visit(url, &deps, &mut seen, &mut order);
+    }
+    Ok(order)
+}
+
+/// Busts the compiled and copied stylesheets in place and updates their own hashes to match.
+pub fn bust_stylesheets(
+    out_dir: &Path,
+    hashes: &mut HashMap<String, String>
+) -> Result<(), Box<dyn Error>> {
+    let stylesheets: Vec<String> = hashes
+        .keys()
+        .filter(|k| is_stylesheet(k))
+        .cloned()
+        .collect();
+    for url in dependency_order(out_dir, &stylesheets)? {
+        let path = out_dir.join(url.trim_start_matches('/'));
+        let css = fs::read_to_string(&path)?;
+        let busted = bust_css(&css, &url, &AssetUrls::Query(hashes));
+        if busted != css {
+            fs::write(&path, &busted)?;
+            hashes.insert(url, hash_bytes(busted.as_bytes()));
+        }
+    }
+    Ok(())
+}
+
+/// Renames assets in `out_dir` to include their content hash, like `style.3fa9c1ab.css`, and
+/// writes `asset-manifest.json` mapping the original urls to the new ones. Stylesheets are renamed
+/// last, in dependency order, so their references can point at the renamed files first.
+pub fn fingerprint_assets(
+    out_dir: &Path,
+    hashes: &HashMap<String, String>
+) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let mut manifest = BTreeMap::new();
+    let (stylesheets, others): (Vec<&String>, Vec<&String>) = hashes
+        .keys()
+        .filter(|k| {
+            Path::new(k.as_str())
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| FINGERPRINTED.iter().any(|f| e.eq_ignore_ascii_case(f)))
+        })
+        .partition(|k| is_stylesheet(k));
+
+    for url in others {
+        let renamed = fingerprinted_url(url, &hashes[url]);
+        rename_asset(out_dir, url, &renamed)?;
+        manifest.insert(url.clone(), renamed);
+    }
+    let stylesheets: Vec<String> = stylesheets.into_iter().cloned().collect();
+    for url in dependency_order(out_dir, &stylesheets)? {
+        let path = out_dir.join(url.trim_start_matches('/'));
+        let css = bust_css(
+            &fs::read_to_string(&path)?,
+            &url,
+            &AssetUrls::Renamed(&manifest)
+        );
+        let renamed = fingerprinted_url(&url, &hash_bytes(css.as_bytes()));
+        fs::write(out_dir.join(renamed.trim_start_matches('/')), css)?;
+        fs::remove_file(path)?;
+        manifest.insert(url, renamed);
+    }
+
+    fs::write(
+        out_dir.join(MANIFEST_NAME),
+        serde_json::to_string_pretty(&manifest)?
+    )?;
+    Ok(manifest)
+}
+
+/// Deletes the fingerprinted files of a previous build so they are not fingerprinted again.
+pub fn remove_fingerprinted(out_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let manifest_path = out_dir.join(MANIFEST_NAME);
+    if !manifest_path.exists() {
+        return Ok(());
+    }
+    let manifest: BTreeMap<String, String> =
+        serde_json::from_str(&fs::read_to_string(&manifest_path)?)?;
+    for renamed in manifest.values() {
+        let path = out_dir.join(renamed.trim_start_matches('/'));
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    fs::remove_file(manifest_path)?;
+    Ok(())
+}
+
+fn is_stylesheet(url: &str) -> bool {
+    Path::new(url)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("css"))
+}
+
+fn fingerprinted_url(url: &str, hash: &str) -> String {
+    let short = &hash[..hash.len().min(8)];
+    match url.rsplit_once('.') {
+        Some((stem, ext)) if !stem.ends_with('/') => format!("{stem}.{short}.{ext}"),
+        _ => format!("{url}.{short}")
+    }
+}
+
+fn rename_asset(out_dir: &Path, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+    fs::rename(
+        out_dir.join(from.trim_start_matches('/')),
+        out_dir.join(to.trim_start_matches('/'))
+    )?;
+    Ok(())
+}
+
+/// Resolves a local url against the document it is referenced from into an asset key.
+pub fn resolve(url: &str, base: &str) -> Option<String> {
+    let joined = match url.strip_prefix('/') {
+        Some(absolute) => Path::new(absolute).to_path_buf(),
+        None => Path::new(base.trim_start_matches('/'))
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(url)
+    };
+    let path = normalize(&joined)?;
+    Some(format!("/{}", path.to_str()?.replace('\\', "/")))
+}
+
+pub fn is_external(url: &str) -> bool {
+    url.starts_with("//")
+        || url
+            .split_once(':')
+            .is_some_and(|(scheme, _)| !scheme.contains('/'))
+}
@@ -1,125 +1,424 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs::{self};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use log::{debug, info, warn};
-use regex::{Captures, Regex};
 use scraper::{Html, Selector};
+use serde::Serialize;
 use tera::{Context, Tera};
-use typst_as_lib::TypstEngine;
+use typst::foundations::{Dict, Value};
 use typst_html::HtmlDocument;
 use xxhash_rust::xxh3::xxh3_64;
 
-use crate::config::Config;
-use crate::file_map::FileMap;
-use crate::meta::{PageMeta, collect_page_meta};
-
-pub fn run_build(dir: PathBuf, minify: bool) -> Result<(), Box<dyn Error>> {
-    let content_path = dir.join("content");
+use crate::assets::{
+    AssetUrls, bust_html, bust_stylesheets, fingerprint_assets, remove_fingerprinted
+};
+use crate::check::check_links;
+use crate::compress::{COMPRESS_MANIFEST, compress_output};
+use crate::config::{Config, CssStyle, LinkConfig, StylesConfig};
+use crate::engine::{Engines, package_roots};
+use crate::file_map::{FileMap, Mount};
+use crate::fonts::{FontFile, engine_fonts, load_fonts, write_web_fonts};
+use crate::frames::{clear_frames, extract_frames, frame_alts, recolor_frames};
+use crate::functions::register_functions;
+use crate::highlight::{Highlighter, write_stylesheet};
+use crate::ignored::{Ignored, MountFilter};
+use crate::images::{ImageSet, image_sizes, process_images, responsive_images, size_images};
+use crate::labels::{collect_labels, label_heading_ids};
+use crate::links::resolve_typ_links;
+use crate::meta::{
+    PageCompiler, PageMeta, collect_page_meta, find_page, find_page_mut, page_url, site_inputs
+};
+use crate::minify::{Minifier, minify_assets};
+use crate::toc::{TocEntry, build_toc};
+use crate::updated::{LastModified, format_rfc3339};
+
+pub fn run_build(
+    dir: &Path,
+    minify: bool,
+    profile: &str,
+    font_paths: &[PathBuf]
+) -> Result<(), Box<dyn Error>> {
     let output_path = dir.join("dist");
     fs::create_dir_all(&output_path)?;
+    clear_frames(&output_path)?;
 
     info!("Reading config");
-    let config_path = dir.join("didactic.toml");
-    let config: Config = if config_path.exists() {
-        Ok::<Config, Box<dyn Error>>(toml::from_str(&fs::read_to_string(config_path)?)?)
-    } else {
-        Err("No manifest file found".into())
-    }?;
+    let config = read_config(dir)?;
+
+    let minify = minify || config.minify.profiles.iter().any(|p| p == profile);
+    let minifier = Minifier::new(&config.minify)?;
 
     info!("Building logical map");
-    let mut file_map = FileMap::with_resolver_base(&dir);
-    file_map.add_directory(&content_path, None)?;
-    config
-        .links
-        .iter()
-        .try_for_each(|i| file_map.add_directory(&dir.join(&i.path), Some(Path::new(&i.slug))))?;
+    let file_map = build_file_map(dir, &config)?;
     debug!("{:?}", &file_map);
 
-    let scss_path = dir.join("templates/main.scss");
-    if scss_path.exists() {
-        info!("Compiling SCSS");
-        let css = grass::from_path(scss_path, &grass::Options::default())?;
-        fs::write(output_path.join("style.css"), css)?;
-    } else {
-        info!("No SCSS found, skipping");
+    compile_styles(dir, &config.styles, &output_path)?;
+    if let Some(highlight) = config.highlight.as_ref().filter(|h| h.stylesheet) {
+        info!("Writing highlight stylesheet");
+        write_stylesheet(highlight, &output_path)?;
     }
 
     info!("Copying static assets");
-    let static_path = dir.join("static");
-    if static_path.exists() {
-        copy_assets(&static_path, &output_path)?;
+    copy_project_assets(dir, &config, &file_map, &output_path)?;
+    let image_sets = match &config.images {
+        Some(images) => {
+            info!("Processing images");
+            process_images(&output_path, &dir.join(".didactic-cache/images"), images)?
+        }
+        None => HashMap::new()
+    };
+    let font_files = load_project_fonts(dir, &config, font_paths, &output_path)?;
+    if minify {
+        info!("Minifying assets");
+        minify_assets(&output_path, &minifier)?;
     }
-    copy_assets(&content_path, &output_path)?;
-    config.links.iter().try_for_each(|i| {
-        let out = output_path.join(&i.slug);
-        fs::create_dir_all(&out)?;
-        copy_assets(&dir.join(&i.path), &out)
-    })?;
-    let asset_hashes = collect_asset_hashes(&output_path, &output_path)?;
+    let image_sizes = image_sizes(&output_path)?;
+    let mut asset_hashes = collect_asset_hashes(&output_path, &output_path)?;
+    let asset_manifest = if config.build.hashed_filenames {
+        info!("Fingerprinting asset filenames");
+        fingerprint_assets(&output_path, &asset_hashes)?
+    } else {
+        bust_stylesheets(&output_path, &mut asset_hashes)?;
+        BTreeMap::new()
+    };
+    let asset_urls = if config.build.hashed_filenames {
+        AssetUrls::Renamed(&asset_manifest)
+    } else {
+        AssetUrls::Query(&asset_hashes)
+    };
     debug!("{:?}", &asset_hashes);
 
     info!("Initializing Tera");
-    let tera = Tera::new(
-        dir.join("templates/**/*.html")
-            .to_str()
-            .expect("Non UTF8 valid path????")
-    )?;
+    let mut templates = Templates::load(dir, &config)?;
 
     info!("Initializing Typst engine");
-    let engine = TypstEngine::builder()
-        .with_file_system_resolver(dir)
-        .fonts(typst_assets::fonts())
-        .build();
+    let inputs = page_inputs(&config, profile, &file_map)?;
+    let fonts = engine_fonts(&font_files);
+    let engines = Engines::build(dir, &config, &file_map, &fonts, &inputs, &package_roots())?;
 
     info!("Compiling content");
+    let last_modified = config.build.git_dates.then(|| LastModified::new(dir));
+    let compiler = PageCompiler {
+        engines: &engines,
+        file_map: &file_map,
+        last_modified: last_modified.as_ref(),
+        inputs
+    };
     let mut cache: HashMap<PathBuf, HtmlDocument> = HashMap::new();
-    let page_metas = collect_page_meta(Path::new(""), &file_map, &engine, &mut cache, true)?;
+    let mut page_metas = collect_page_meta(Path::new(""), &compiler, &mut cache, true)?;
+    apply_link_titles(&mut page_metas, &config);
     debug!("{:?}", &page_metas);
 
-    info!("Generating RSS feed");
-    generate_rss(&page_metas, &config, &output_path)?;
+    for tera in templates.all_mut() {
+        register_functions(tera, &asset_hashes, &asset_urls, &file_map, &page_metas);
+    }
+
+    info!("Generating feeds");
+    let feed_pages = without_links(&page_metas, &config, &|l| l.feeds);
+    generate_rss(&feed_pages, &config, &output_path)?;
+    generate_atom(&feed_pages, &config, &output_path)?;
+    generate_sitemap(&page_metas, &config, &output_path)?;
+
+    let highlighter = config.highlight.is_some().then(Highlighter::load);
 
     info!("Processing templates");
     process_typst_files(
         Path::new(""),
         &file_map,
         &output_path,
-        &tera,
+        &templates,
         &page_metas,
+        &without_links(&page_metas, &config, &|l| l.menu),
         &config,
+        &engines,
         &mut cache,
         &asset_hashes,
-        minify
+        &asset_urls,
+        &image_sets,
+        &image_sizes,
+        highlighter.as_ref(),
+        minify.then_some(&minifier)
     )?;
 
+    if config.build.check_links {
+        info!("Checking links");
+        check_links(&output_path, &file_map)?;
+    }
+
+    if config.compress.is_some() {
+        info!("Compressing output");
+    }
+    let manifest = dir.join(COMPRESS_MANIFEST);
+    compress_output(&output_path, &manifest, config.compress.as_ref())?;
+
     info!("Build complete");
     Ok(())
 }
 
+/// Copies `static/`, the assets next to the content and those of every link into `out_dir`,
+/// after clearing the fingerprinted files of the previous build.
+fn copy_project_assets(
+    dir: &Path,
+    config: &Config,
+    file_map: &FileMap,
+    out_dir: &Path
+) -> Result<(), Box<dyn Error>> {
+    remove_fingerprinted(out_dir)?;
+    let static_path = dir.join("static");
+    let mut copied = HashMap::new();
+    if static_path.exists() {
+        copy_assets(
+            &static_path,
+            out_dir,
+            file_map.ignored(),
+            &mut copied,
+            &Mount::default()
+        )?;
+    }
+    copy_assets(
+        &dir.join("content"),
+        out_dir,
+        file_map.ignored(),
+        &mut copied,
+        &Mount::default()
+    )?;
+    config.links.iter().try_for_each(|i| {
+        let out = out_dir.join(&i.slug);
+        fs::create_dir_all(&out)?;
+        copy_assets(
+            &dir.join(&i.path),
+            &out,
+            file_map.ignored(),
+            &mut copied,
+            &link_mount(dir, i)?
+        )
+    })
+}
+
+/// Loads the fonts from `[typst] fonts` and `--font-path`, writing them out as web fonts when
+/// configured.
+fn load_project_fonts(
+    dir: &Path,
+    config: &Config,
+    font_paths: &[PathBuf],
+    out_dir: &Path
+) -> Result<Vec<FontFile>, Box<dyn Error>> {
+    let font_dirs: Vec<PathBuf> = config
+        .typst
+        .fonts
+        .iter()
+        .map(|f| dir.join(f))
+        .chain(font_paths.iter().cloned())
+        .collect();
+    let font_files = load_fonts(&font_dirs)?;
+    if config.typst.web_fonts {
+        info!("Copying web fonts");
+        write_web_fonts(&font_files, out_dir)?;
+    }
+    Ok(font_files)
+}
+
+/// The site inputs plus the cross page label index, when there are any labels.
+fn page_inputs(config: &Config, profile: &str, file_map: &FileMap) -> Result<Dict, Box<dyn Error>> {
+    let mut inputs = site_inputs(config, profile);
+    let labels = collect_labels(file_map)?;
+    if !labels.is_empty() {
+        info!("Found {} cross page labels", labels.len());
+        inputs.insert("labels".into(), Value::Dict(labels));
+    }
+    Ok(inputs)
+}
+
+/// Replaces the titles of link sections that set `title`.
+fn apply_link_titles(page_metas: &mut [PageMeta], config: &Config) {
+    for link in &config.links {
+        let url = page_url(&Path::new(&link.slug).join("index.typ"));
+        if let (Some(title), Some(section)) = (&link.title, find_page_mut(page_metas, &url)) {
+            section.title.clone_from(title);
+        }
+    }
+}
+
+fn compile_styles(dir: &Path, styles: &StylesConfig, out_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let load_paths: Vec<PathBuf> = styles.load_paths.iter().map(|p| dir.join(p)).collect();
+    let options = grass::Options::default()
+        .style(match styles.style {
+            CssStyle::Expanded => grass::OutputStyle::Expanded,
+            CssStyle::Compressed => grass::OutputStyle::Compressed
+        })
+        .load_paths(&load_paths);
+
+    for entry in &styles.entries {
+        let input = dir.join(&entry.input);
+        if !input.exists() {
+            if entry.optional {
+                info!("No SCSS found at {}, skipping", entry.input);
+                continue;
+            }
+            return Err(format!("Style entry {} does not exist", entry.input).into());
+        }
+        info!("Compiling {}", entry.input);
+        let css = grass::from_path(&input, &options)?;
+        let out_path = out_dir.join(&entry.output);
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(out_path, css)?;
+    }
+    Ok(())
+}
+
+pub fn read_config(dir: &Path) -> Result<Config, Box<dyn Error>> {
+    let config_path = dir.join("didactic.toml");
+    if config_path.exists() {
+        Ok(toml::from_str(&fs::read_to_string(config_path)?)?)
+    } else {
+        Err("No manifest file found".into())
+    }
+}
+
+pub fn build_file_map(dir: &Path, config: &Config) -> Result<FileMap, Box<dyn Error>> {
+    let ignored = Ignored::load(dir, &config.build.ignore)?;
+    let libraries = config
+        .typst
+        .libraries
+        .iter()
+        .chain(&config.typst.prelude)
+        .map(|l| dir.join(l));
+    let mut file_map = FileMap::with_resolver_base(dir)
+        .ignoring(ignored)
+        .with_libraries(libraries);
+    file_map.add_directory(&dir.join("content"), None, &Mount::default())?;
+    config.links.iter().try_for_each(|i| {
+        file_map.add_directory(
+            &dir.join(&i.path),
+            Some(Path::new(&i.slug)),
+            &link_mount(dir, i)?
+        )
+    })?;
+    Ok(file_map)
+}
+
+/// How a link is mounted. Its own template directory is left out when it lives inside the link.
+fn link_mount(dir: &Path, link: &LinkConfig) -> Result<Mount, Box<dyn Error>> {
+    let mut filter = MountFilter::new(&dir.join(&link.path), &link.include, &link.exclude)?;
+    if let Some(templates) = &link.templates {
+        filter = filter.skipping(&dir.join(templates));
+    }
+    Ok(Mount {
+        shadow: link.overrides,
+        filter
+    })
+}
+
+/// The pages without the sections of links that `keep` rejects, at any depth.
+fn without_links(
+    pages: &[PageMeta],
+    config: &Config,
+    keep: &impl Fn(&LinkConfig) -> bool
+) -> Vec<PageMeta> {
+    pages
+        .iter()
+        .filter(|p| {
+            config.links.iter().all(|l| {
+                let section = format!("/{}", l.slug.trim_matches('/'));
+                keep(l) || !(p.url == section || p.url.starts_with(&format!("{section}/")))
+            })
+        })
+        .map(|p| PageMeta {
+            children: without_links(&p.children, config, keep),
+            ..p.clone()
+        })
+        .collect()
+}
+
+/// The project templates, plus one set for every link with its own template directory. Those
+/// fall back to the project templates for anything they do not define, and can extend them.
+pub struct Templates {
+    project: Tera,
+    links: Vec<(PathBuf, Tera)>
+}
+
+impl Templates {
+    pub fn load(dir: &Path, config: &Config) -> Result<Self, Box<dyn Error>> {
+        let glob = |templates: PathBuf| {
+            templates
+                .join("**/*.html")
+                .to_str()
+                .expect("Non UTF8 valid path????")
+                .to_string()
+        };
+        let project = Tera::new(&glob(dir.join("templates")))?;
+        let mut links = Vec::new();
+        for link in &config.links {
+            if let Some(templates) = &link.templates {
+                // Inheritance is resolved only once the project templates are in, so a link's
+                // templates can extend the project's `base.html`
+                let mut tera = Tera::parse(&glob(dir.join(templates)))?;
+                tera.extend(&project)?;
+                tera.build_inheritance_chains()?;
+                links.push((PathBuf::from(&link.slug), tera));
+            }
+        }
+        Ok(Self { project, links })
+    }
+
+    pub fn for_page(&self, logical: &Path) -> &Tera {
+        self.links
+            .iter()
+            .find(|(slug, _)| logical.starts_with(slug))
+            .map_or(&self.project, |(_, tera)| tera)
+    }
+
+    fn all_mut(&mut self) -> impl Iterator<Item = &mut Tera> {
+        std::iter::once(&mut self.project).chain(self.links.iter_mut().map(|(_, tera)| tera))
+    }
+}
+
+#[derive(Serialize)]
+struct PageContext<'a> {
+    #[serde(flatten)]
+    meta: Option<&'a PageMeta>,
+    toc: Vec<TocEntry>
+}
+
 #[allow(clippy::too_many_arguments)]
 fn process_typst_files(
     prefix: &Path,
     file_map: &FileMap,
     out_dir: &Path,
-    tera: &Tera,
+    templates: &Templates,
     page_metas: &[PageMeta],
+    menu: &[PageMeta],
     config: &Config,
+    engines: &Engines,
     cache: &mut HashMap<PathBuf, HtmlDocument>,
     asset_hashes: &HashMap<String, String>,
-    minify: bool
+    asset_urls: &AssetUrls,
+    image_sets: &HashMap<String, ImageSet>,
+    image_sizes: &HashMap<String, (u32, u32)>,
+    highlighter: Option<&Highlighter>,
+    minify: Option<&Minifier>
 ) -> Result<(), Box<dyn Error>> {
     for dir in file_map.subdirs_at(prefix) {
         process_typst_files(
             &dir,
             file_map,
             out_dir,
-            tera,
+            templates,
             page_metas,
+            menu,
             config,
+            engines,
             cache,
             asset_hashes,
+            asset_urls,
+            image_sets,
+            image_sizes,
+            highlighter,
             minify
         )?;
     }
@@ -127,12 +426,26 @@ fn process_typst_files(
     for logical in file_map.typ_files_at(prefix) {
         info!("Rendering {}", logical.display());
 
-        let doc = cache
+        let mut doc = cache
             .remove(logical)
             .ok_or_else(|| format!("no cached doc for {}", logical.display()))?;
 
-        let typst_html =
-            extract_body_content(&typst_html::html(&doc).map_err(|e| format!("{e:?}"))?);
+        label_heading_ids(&mut doc.root);
+        let mut body = extract_body_content(&typst_html::html(&doc).map_err(|e| format!("{e:?}"))?);
+        if let Some(mode) = config.build.frame_colors {
+            body = recolor_frames(&body, mode)?;
+        }
+        if config.build.extract_frames {
+            let real = file_map
+                .get_real(logical)
+                .ok_or_else(|| format!("{} is not mapped", logical.display()))?;
+            let alts = frame_alts(&doc.root, &engines.page_source(real)?);
+            body = extract_frames(&body, &alts, out_dir)?;
+        }
+        if let Some(highlighter) = highlighter {
+            body = highlighter.highlight(&body)?;
+        }
+        let (typst_html, toc) = build_toc(&resolve_typ_links(&body, logical, file_map)?)?;
 
         let mut out_path = out_dir.join(logical);
         out_path.set_extension("html");
@@ -153,17 +466,37 @@ fn process_typst_files(
         let mut context = Context::new();
         context.insert("asset_hashes", asset_hashes);
         context.insert("current_section", &current_section);
-        context.insert("menu", &page_metas);
+        context.insert(
+            "page",
+            &PageContext {
+                meta: find_page(page_metas, &page_url(logical)),
+                toc
+            }
+        );
+        context.insert("menu", menu);
         context.insert("content", &typst_html);
         context.insert("site", &config.site);
 
-        let rendered =
-            bust_image_urls(&tera.render("index.html", &context)?, asset_hashes).into_bytes();
+        let out_url = format!(
+            "/{}",
+            logical
+                .with_extension("html")
+                .to_str()
+                .unwrap()
+                .replace('\\', "/")
+        );
+        let mut rendered = templates.for_page(logical).render("index.html", &context)?;
+        if let Some(images) = &config.images {
+            rendered = responsive_images(&rendered, &out_url, image_sets, &images.sizes)?;
+        }
+        let rendered = size_images(&rendered, &out_url, image_sizes)?;
+        let rendered = bust_html(&rendered, &out_url, asset_urls)?.into_bytes();
 
-        let minified = if minify {
+        let minified = if let Some(minifier) = minify
+            && minifier.allows(out_path.strip_prefix(out_dir)?)
+        {
             debug!("Minifying");
-            let cfg = minify_html::Cfg::new();
-            minify_html::minify(&rendered, &cfg)
+            minify_html::minify(&rendered, &minifier.cfg)
         } else {
             rendered
         };
@@ -254,7 +587,122 @@ fn generate_rss(pages: &[PageMeta], config: &Config, out_dir: &Path) -> Result<(
     Ok(())
 }
 
-fn escape_xml(s: &str) -> String {
+/// Writes `atom.xml` with the same pages as the RSS feed. An entry is updated when its source last
+/// changed, with `[build] git_dates`, and otherwise when it was published.
+fn generate_atom(
+    pages: &[PageMeta],
+    config: &Config,
+    out_dir: &Path
+) -> Result<(), Box<dyn Error>> {
+    let base = config.site.base_url.trim_end_matches('/');
+    let entries: Vec<(&PageMeta, u64)> = pages
+        .iter()
+        .filter(|p| p.children.is_empty())
+        .chain(pages.iter().flat_map(|p| p.children.iter()))
+        .filter_map(|p| Some((p, p.modified.or(p.published)?)))
+        .collect();
+    let feed_updated = entries
+        .iter()
+        .map(|(_, updated)| *updated)
+        .max()
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+
+    let entries: String = entries
+        .iter()
+        .map(|(p, updated)| {
+            let published = p
+                .published
+                .map(|d| format!("\n    <published>{}</published>", format_rfc3339(d)))
+                .unwrap_or_default();
+            format!(
+                r#"  <entry>
+    <title>{}</title>
+    <link href="{}{}"/>
+    <id>{}{}</id>
+    <updated>{}</updated>{}
+  </entry>"#,
+                escape_xml(&p.title),
+                base,
+                p.url,
+                base,
+                p.url,
+                format_rfc3339(*updated),
+                published
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let atom = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>{}</title>
+  <subtitle>{}</subtitle>
+  <link href="{}/"/>
+  <link rel="self" href="{}/atom.xml"/>
+  <id>{}/</id>
+  <updated>{}</updated>
+  <author>
+    <name>{}</name>
+  </author>
+{}
+</feed>"#,
+        escape_xml(&config.site.title),
+        escape_xml(&config.site.description),
+        base,
+        base,
+        base,
+        format_rfc3339(feed_updated),
+        escape_xml(&config.site.author),
+        entries
+    );
+
+    fs::write(out_dir.join("atom.xml"), atom)?;
+    Ok(())
+}
+
+/// Writes `sitemap.xml` listing every page, with `lastmod` from `[build] git_dates`.
+fn generate_sitemap(
+    pages: &[PageMeta],
+    config: &Config,
+    out_dir: &Path
+) -> Result<(), Box<dyn Error>> {
+    fn push_urls(pages: &[PageMeta], base: &str, out: &mut Vec<String>) {
+        for page in pages {
+            let lastmod = page
+                .modified
+                .map(|m| format!("\n    <lastmod>{}</lastmod>", format_rfc3339(m)))
+                .unwrap_or_default();
+            out.push(format!(
+                "  <url>\n    <loc>{}{}</loc>{}\n  </url>",
+                base,
+                escape_xml(&page.url),
+                lastmod
+            ));
+            push_urls(&page.children, base, out);
+        }
+    }
+
+    let base = config.site.base_url.trim_end_matches('/');
+    let mut urls = Vec::new();
+    push_urls(pages, base, &mut urls);
+    let sitemap = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+{}
+</urlset>"#,
+        urls.join("\n")
+    );
+
+    fs::write(out_dir.join("sitemap.xml"), sitemap)?;
+    Ok(())
+}
+
+pub fn escape_xml(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
@@ -272,9 +720,12 @@ fn extract_body_content(html: &str) -> String {
         .map_or_else(|| html.to_string(), |body| body.inner_html())
 }
 
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    format!("{:x}", xxh3_64(bytes))
+}
+
 fn hash_file(path: &Path) -> Result<String, Box<dyn Error>> {
-    let contents = fs::read(path)?;
-    Ok(format!("{:x}", xxh3_64(&contents)))
+    Ok(hash_bytes(&fs::read(path)?))
 }
 
 fn collect_asset_hashes(
@@ -301,36 +752,48 @@ fn collect_asset_hashes(
     Ok(hashes)
 }
 
-fn bust_image_urls(html: &str, asset_hashes: &HashMap<String, String>) -> String {
-    let re = Regex::new(r#"<img([^>]*?)src="(/[^"?]+)"([^>]*?)>"#).unwrap();
-    re.replace_all(html, |caps: &Captures| {
-        let before = &caps[1];
-        let src = &caps[2];
-        let after = &caps[3];
-        if let Some(hash) = asset_hashes.get(src) {
-            format!(r#"<img{before}src="{src}?v={hash}"{after}>"#)
-        } else {
-            caps[0].to_string()
-        }
-    })
-    .to_string()
-}
-
-fn copy_assets(src: &Path, dst: &Path) -> Result<(), Box<dyn Error>> {
+/// Copies everything but sources from `src` into `dst`. `copied` maps every file copied so far to
+/// its source, so two sources for one output file are an error unless the mount shadows it.
+fn copy_assets(
+    src: &Path,
+    dst: &Path,
+    ignored: &Ignored,
+    copied: &mut HashMap<PathBuf, PathBuf>,
+    mount: &Mount
+) -> Result<(), Box<dyn Error>> {
     for entry in fs::read_dir(src)? {
         let entry = entry?;
         let path = entry.path();
+        if ignored.is_ignored(&path) {
+            continue;
+        }
         if path.is_dir() {
+            if !mount.filter.walks(&path) {
+                continue;
+            }
             let dest_dir = dst.join(path.file_name().unwrap());
             fs::create_dir_all(&dest_dir)?;
-            copy_assets(&path, &dest_dir)?;
+            copy_assets(&path, &dest_dir, ignored, copied, mount)?;
         } else {
             let ext = path.extension().and_then(|s| s.to_str());
 
             if let Some("typ" | "toml" | "scss") = ext {
-            } else {
+            } else if mount.filter.allows(&path) {
                 let dst = dst.join(path.file_name().unwrap());
+                if let Some(existing) = copied.get(&dst) {
+                    if !mount.shadow {
+                        return Err(format!(
+                            "{} and {} both copy to {}",
+                            existing.display(),
+                            path.display(),
+                            dst.display()
+                        )
+                        .into());
+                    }
+                    debug!("{} shadows {}", path.display(), existing.display());
+                }
                 fs::copy(&path, &dst)?;
+                copied.insert(dst, path);
             }
         }
     }
@@ -0,0 +1,161 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self};
+use std::path::{Path, PathBuf};
+
+use log::{error, info};
+use scraper::{Html, Selector};
+
+use crate::assets::is_external;
+use crate::build::{build_file_map, read_config};
+use crate::file_map::FileMap;
+use crate::links::normalize;
+
+struct Page {
+    ids: HashSet<String>,
+    refs: Vec<String>
+}
+
+/// Checks an already built site in `dir/dist`.
+pub fn run_check(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let config = read_config(dir)?;
+    let file_map = build_file_map(dir, &config)?;
+    check_links(&dir.join("dist"), &file_map)
+}
+
+/// Verifies that every internal `href`, `src` and `srcset` url in the rendered pages points at a file in
+/// `out_dir`, and that fragments exist as an `id` on the target page. Broken links are reported
+/// against the `.typ` file the page was rendered from.
+pub fn check_links(out_dir: &Path, file_map: &FileMap) -> Result<(), Box<dyn Error>> {
+    let mut pages = HashMap::new();
+    collect_pages(out_dir, out_dir, &mut pages)?;
+
+    let mut broken = 0;
+    let mut rels: Vec<&PathBuf> = pages.keys().collect();
+    rels.sort();
+    for rel in rels {
+        for reference in &pages[rel].refs {
+            if let Err(reason) = check_reference(reference, rel, out_dir, &pages) {
+                let logical = rel.with_extension("typ");
+                let source = file_map.get_real(&logical).unwrap_or(rel);
+                error!(
+                    "{}: broken link {} ({})",
+                    source.display(),
+                    reference,
+                    reason
+                );
+                broken += 1;
+            }
+        }
+    }
+
+    if broken == 0 {
+        info!("No broken links in {} pages", pages.len());
+        Ok(())
+    } else {
+        Err(format!("Found {broken} broken links").into())
+    }
+}
+
+fn collect_pages(
+    dir: &Path,
+    base: &Path,
+    pages: &mut HashMap<PathBuf, Page>
+) -> Result<(), Box<dyn Error>> {
+    let id_selector = Selector::parse("[id]").unwrap();
+    let ref_selector = Selector::parse("[href], [src], [srcset]").unwrap();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_pages(&path, base, pages)?;
+        } else if path.extension().and_then(|s| s.to_str()) == Some("html") {
+            let document = Html::parse_document(&fs::read_to_string(&path)?);
+            let ids = document
+                .select(&id_selector)
+                .filter_map(|e| e.value().id())
+                .map(str::to_string)
+                .collect();
+            let refs = document
+                .select(&ref_selector)
+                .flat_map(|e| {
+                    let srcset = e.value().attr("srcset").into_iter().flat_map(|s| {
+                        s.split(',')
+                            .filter_map(|candidate| candidate.split_whitespace().next())
+                    });
+                    [e.value().attr("href"), e.value().attr("src")]
+                        .into_iter()
+                        .flatten()
+                        .chain(srcset)
+                })
+                .map(str::to_string)
+                .collect();
+            pages.insert(path.strip_prefix(base)?.to_path_buf(), Page { ids, refs });
+        }
+    }
+    Ok(())
+}
+
+fn check_reference(
+    reference: &str,
+    page: &Path,
+    out_dir: &Path,
+    pages: &HashMap<PathBuf, Page>
+) -> Result<(), String> {
+    if is_external(reference) {
+        return Ok(());
+    }
+    let (path, fragment) = match reference.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (reference, None)
+    };
+    let path = percent_decode(path.split('?').next().unwrap_or_default());
+    let path = path.as_str();
+
+    let target = if path.is_empty() {
+        page.to_path_buf()
+    } else {
+        let joined = match path.strip_prefix('/') {
+            Some(absolute) => PathBuf::from(absolute),
+            None => page.parent().unwrap_or(Path::new("")).join(path)
+        };
+        let mut target = normalize(&joined).ok_or("escapes the output directory")?;
+        if path.ends_with('/') || out_dir.join(&target).is_dir() {
+            target.push("index.html");
+        }
+        if !out_dir.join(&target).is_file() {
+            return Err("missing file".to_string());
+        }
+        target
+    };
+
+    match (fragment, pages.get(&target)) {
+        (Some(fragment), Some(page)) if !fragment.is_empty() && !page.ids.contains(fragment) => {
+            Err(format!("no id {fragment} on {}", target.display()))
+        }
+        _ => Ok(())
+    }
+}
+
+/// Decodes `%XX` escapes, leaving malformed ones as they are.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,20 @@
+// Attaches line numbers and highlighted lines to a raw block, read by didactic's syntax
+// highlighting. Lines to highlight are numbers, or `(start, end)` pairs for ranges.
+
+#let code(numbers: false, highlight: (), it) = context {
+  if target() != "html" or not it.block {
+    return it
+  }
+  let attrs = (:)
+  if it.lang != none {
+    attrs.insert("data-lang", it.lang)
+  }
+  if numbers {
+    attrs.insert("data-line-numbers", "")
+  }
+  if highlight.len() > 0 {
+    let lines = highlight.map(l => if type(l) == array { str(l.at(0)) + "-" + str(l.at(1)) } else { str(l) })
+    attrs.insert("data-highlight", lines.join(","))
+  }
+  html.elem("pre", attrs: attrs, html.elem("code", it.text))
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::{self};
+use std::io::Write;
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use log::debug;
+
+use crate::config::CompressConfig;
+
+const COMPRESSED: [&str; 7] = ["html", "css", "js", "mjs", "xml", "svg", "json"];
+
+/// Lists the siblings the last build wrote, relative to the project root.
+pub const COMPRESS_MANIFEST: &str = ".didactic-cache/compressed.json";
+
+type Encoder = fn(&[u8]) -> std::io::Result<Vec<u8>>;
+
+/// Writes `.gz` and `.br` siblings next to every text file in `out_dir` of at least `min_size`
+/// bytes. Encodings that do not make a file smaller are skipped. The siblings written are listed
+/// in `manifest`, so those an earlier build wrote are removed once they are no longer written and
+/// can not be served instead. Siblings the build did not write, like a shipped `vendor.js.gz`, are
+/// left alone.
+pub fn compress_output(
+    out_dir: &Path,
+    manifest: &Path,
+    config: Option<&CompressConfig>
+) -> Result<(), Box<dyn Error>> {
+    let previous: BTreeSet<String> = if manifest.exists() {
+        serde_json::from_str(&fs::read_to_string(manifest)?)?
+    } else {
+        BTreeSet::new()
+    };
+    let mut written = BTreeSet::new();
+    if let Some(config) = config {
+        compress_dir(out_dir, out_dir, config, &previous, &mut written)?;
+    }
+
+    for sibling in previous.difference(&written) {
+        let path = out_dir.join(sibling);
+        if path.exists() {
+            debug!("Removing stale {}", path.display());
+            fs::remove_file(path)?;
+        }
+    }
+    if written.is_empty() {
+        if manifest.exists() {
+            fs::remove_file(manifest)?;
+        }
+    } else {
+        if let Some(parent) = manifest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(manifest, serde_json::to_string_pretty(&written)?)?;
+    }
+    Ok(())
+}
+
+/// Compresses the files under `dir`, adding the siblings written to `written` by their path
+/// relative to `out_dir`. Existing siblings not in `previous` were not written by didactic and are
+/// not overwritten.
+fn compress_dir(
+    out_dir: &Path,
+    dir: &Path,
+    config: &CompressConfig,
+    previous: &BTreeSet<String>,
+    written: &mut BTreeSet<String>
+) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            compress_dir(out_dir, &path, config, previous, written)?;
+            continue;
+        }
+        if !path
+            .extension()
+            .and_then(|s| s.to_str())
+            .is_some_and(|e| COMPRESSED.contains(&e))
+        {
+            continue;
+        }
+        let data = fs::read(&path)?;
+        if (data.len() as u64) < config.min_size {
+            continue;
+        }
+
+        let encodings: [(&str, bool, Encoder); 2] =
+            [("gz", config.gzip, gzip), ("br", config.brotli, brotli)];
+        for (ext, enabled, encode) in encodings {
+            let mut sibling = path.clone().into_os_string();
+            sibling.push(format!(".{ext}"));
+            let sibling = Path::new(&sibling);
+            let relative = sibling
+                .strip_prefix(out_dir)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            if !enabled || (sibling.exists() && !previous.contains(&relative)) {
+                continue;
+            }
+            let compressed = encode(&data)?;
+            if compressed.len() < data.len() {
+                fs::write(sibling, compressed)?;
+                written.insert(relative);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn brotli(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    {
+        let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 11, 22);
+        writer.write_all(data)?;
+    }
+    Ok(out)
+}
@@ -4,6 +4,20 @@ use serde::{Deserialize, Serialize};
 pub struct Config {
     pub site: SiteConfig,
     #[serde(default)]
+    pub build: BuildConfig,
+    #[serde(default)]
+    pub images: Option<ImageConfig>,
+    #[serde(default)]
+    pub highlight: Option<HighlightConfig>,
+    #[serde(default)]
+    pub styles: StylesConfig,
+    #[serde(default)]
+    pub minify: MinifyConfig,
+    #[serde(default)]
+    pub compress: Option<CompressConfig>,
+    #[serde(default)]
+    pub typst: TypstConfig,
+    #[serde(default)]
     pub links: Vec<LinkConfig>
 }
 
@@ -16,8 +30,259 @@ pub struct SiteConfig {
     pub description: String
 }
 
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct BuildConfig {
+    /// Take `page.updated` from the last git commit touching each source file
+    #[serde(default)]
+    pub git_dates: bool,
+    /// Run `didactic check` on the output after every build
+    #[serde(default)]
+    pub check_links: bool,
+    /// Rename assets to include their content hash instead of appending `?v=hash`
+    #[serde(default)]
+    pub hashed_filenames: bool,
+    /// Move `html.frame` SVGs out of the pages into deduplicated files under `frames/`
+    #[serde(default)]
+    pub extract_frames: bool,
+    /// Rewrite the colors in `html.frame` SVGs to follow the text color
+    #[serde(default)]
+    pub frame_colors: Option<FrameColors>,
+    /// Gitignore style patterns left out of the pages and copied assets, on top of
+    /// `.didacticignore`
+    #[serde(default)]
+    pub ignore: Vec<String>
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameColors {
+    /// Black becomes `currentColor`, other colors are kept
+    CurrentColor,
+    /// Every color is read from a `--frame-<hex>` CSS variable, black defaulting to `currentColor`
+    Variables
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct TypstConfig {
+    #[serde(default)]
+    pub math: MathOutput,
+    /// Typst files, relative to the project root, that are imported by pages but not rendered
+    #[serde(default)]
+    pub libraries: Vec<String>,
+    /// A typst file, relative to the project root, whose definitions every page imports. Show and
+    /// set rules only reach pages through its `rules` function, which is applied to every page
+    #[serde(default)]
+    pub prelude: Option<String>,
+    /// Extra values passed to every page through `sys.inputs`
+    #[serde(default)]
+    pub inputs: toml::Table,
+    /// Directories of fonts to compile with, relative to the project root
+    #[serde(default)]
+    pub fonts: Vec<String>,
+    /// Copy every single face font from `fonts` to the output directory along with a `fonts.css`,
+    /// including fonts no page uses
+    #[serde(default)]
+    pub web_fonts: bool,
+    /// A directory of vendored packages laid out as `<namespace>/<name>/<version>`, relative to
+    /// the project root, searched before the local typst package directories
+    #[serde(default)]
+    pub packages: Option<String>
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MathOutput {
+    /// Leave equations to typst, which drops them from HTML unless wrapped in `html.frame`
+    #[default]
+    Typst,
+    /// Convert equations to MathML, falling back to `html.frame` for anything unsupported
+    Mathml
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ImageConfig {
+    /// Widths to resize to, images are never scaled up
+    #[serde(default = "default_widths")]
+    pub widths: Vec<u32>,
+    #[serde(default)]
+    pub format: ImageOutputFormat,
+    /// Value of the `sizes` attribute added to images
+    #[serde(default = "default_sizes")]
+    pub sizes: String
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageOutputFormat {
+    #[default]
+    Webp,
+    Avif,
+    Original
+}
+
+fn default_widths() -> Vec<u32> {
+    vec![480, 960, 1920]
+}
+
+fn default_sizes() -> String {
+    "100vw".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HighlightConfig {
+    /// Syntect theme the stylesheet is generated from
+    #[serde(default = "default_theme")]
+    pub theme: String,
+    /// Theme used instead when the browser prefers a dark color scheme
+    #[serde(default)]
+    pub dark_theme: Option<String>,
+    /// Write `highlight.css` to the output directory
+    #[serde(default)]
+    pub stylesheet: bool
+}
+
+fn default_theme() -> String {
+    "InspiredGitHub".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StylesConfig {
+    /// SCSS files to compile, relative to the project root
+    #[serde(default = "default_style_entries")]
+    pub entries: Vec<StyleEntry>,
+    /// Extra directories searched by `@use` and `@import`, relative to the project root
+    #[serde(default)]
+    pub load_paths: Vec<String>,
+    #[serde(default)]
+    pub style: CssStyle
+}
+
+impl Default for StylesConfig {
+    fn default() -> Self {
+        Self {
+            entries: default_style_entries(),
+            load_paths: Vec::new(),
+            style: CssStyle::default()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StyleEntry {
+    pub input: String,
+    /// Path of the compiled stylesheet, relative to the output directory
+    pub output: String,
+    /// Only the default entry may be missing, listed entries must exist
+    #[serde(skip)]
+    pub optional: bool
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CssStyle {
+    #[default]
+    Expanded,
+    Compressed
+}
+
+fn default_style_entries() -> Vec<StyleEntry> {
+    vec![StyleEntry {
+        input: "templates/main.scss".to_string(),
+        output: "style.css".to_string(),
+        optional: true
+    }]
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MinifyConfig {
+    /// Build profiles that minify without passing `--minify`
+    #[serde(default)]
+    pub profiles: Vec<String>,
+    #[serde(default)]
+    pub keep_comments: bool,
+    #[serde(default)]
+    pub keep_closing_tags: bool,
+    /// Keep server side include comments like `<!--# include file="header.html" -->`
+    #[serde(default)]
+    pub keep_ssi_comments: bool,
+    /// Minify stylesheets and inline `<style>`
+    #[serde(default = "default_true")]
+    pub minify_css: bool,
+    /// Minify scripts and inline `<script>`
+    #[serde(default = "default_true")]
+    pub minify_js: bool,
+    /// Globs of output paths, relative to `dist`, that are never minified
+    #[serde(default)]
+    pub exclude: Vec<String>
+}
+
+impl Default for MinifyConfig {
+    fn default() -> Self {
+        Self {
+            profiles: Vec::new(),
+            keep_comments: false,
+            keep_closing_tags: false,
+            keep_ssi_comments: false,
+            minify_css: true,
+            minify_js: true,
+            exclude: Vec::new()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CompressConfig {
+    #[serde(default = "default_true")]
+    pub gzip: bool,
+    #[serde(default = "default_true")]
+    pub brotli: bool,
+    /// Files smaller than this many bytes are not compressed
+    #[serde(default = "default_min_size")]
+    pub min_size: u64
+}
+
+fn default_min_size() -> u64 {
+    1024
+}
+
+fn default_true() -> bool {
+    true
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct LinkConfig {
     pub slug: String,
-    pub path: String
+    pub path: String,
+    /// Let this mount replace content files and assets with the same logical path
+    #[serde(default, rename = "override")]
+    pub overrides: bool,
+    /// Only files matching one of these globs, relative to `path`, are used
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Files matching any of these globs, relative to `path`, are left out
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// List the mount's section in `menu`
+    #[serde(default = "default_true")]
+    pub menu: bool,
+    /// Include the mount's pages in the RSS feed
+    #[serde(default = "default_true")]
+    pub feeds: bool,
+    /// Templates used for the mount's pages, falling back to the project templates
+    #[serde(default)]
+    pub templates: Option<String>,
+    /// Title of the mount's section instead of the title of its index
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub root: TypstRoot
+}
+
+/// Where absolute imports in a mount's `.typ` files resolve from.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TypstRoot {
+    #[default]
+    Project,
+    Mount
 }
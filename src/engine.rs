@@ -0,0 +1,403 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{self};
+use std::path::{Path, PathBuf};
+
+use typst::diag::{FileError, FileResult, PackageError, SourceDiagnostic};
+use typst::foundations::{Bytes, Dict};
+use typst::syntax::package::PackageSpec;
+use typst::syntax::{FileId, Source, Span, VirtualPath};
+use typst::text::Font;
+use typst_as_lib::cached_file_resolver::{CachedFileResolver, IntoCachedFileResolver};
+use typst_as_lib::file_resolver::{DEFAULT_PACKAGES_SUBDIR, FileResolver, FileSystemResolver};
+use typst_as_lib::{TypstAsLibError, TypstEngine};
+use typst_html::HtmlDocument;
+
+use crate::config::{Config, MathOutput, TypstRoot};
+use crate::file_map::FileMap;
+
+/// Typst modules shipped with didactic, importable from `/@didactic/<name>`.
+const MODULE_DIR: &str = "@didactic";
+const MODULES: [(&str, &str); 3] = [
+    ("code.typ", include_str!("code.typ")),
+    ("mathml.typ", include_str!("mathml.typ")),
+    ("refs.typ", include_str!("refs.typ"))
+];
+
+/// Imports the generated `/@didactic/preamble.typ` and applies its rules. Prepended to every page
+/// as a single line, which diagnostics leave out when counting lines.
+const PREAMBLE_IMPORT: &str = "#import \"/@didactic/preamble.typ\": *; #show: didactic-rules\n";
+/// The configured prelude is served from here, so every engine finds it whatever its root.
+const PRELUDE: &str = "@didactic/prelude.typ";
+
+/// The engine for the project, plus one for every link whose `.typ` files resolve absolute paths
+/// from the link's own directory.
+pub struct Engines {
+    dir: PathBuf,
+    /// Text prepended to every page, empty when there is nothing to inject
+    header: String,
+    prelude: Option<PathBuf>,
+    project: TypstEngine,
+    links: Vec<(PathBuf, TypstEngine)>
+}
+
+impl Engines {
+    /// `inputs` are the `sys.inputs` pages are compiled with. References are resolved against
+    /// their cross page labels. Packages are looked up in the configured `packages` directory, then in
+    /// `package_roots` in order.
+    pub fn build(
+        dir: &Path,
+        config: &Config,
+        file_map: &FileMap,
+        fonts: &[Font],
+        inputs: &Dict,
+        package_roots: &[PathBuf]
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut modules = Vec::new();
+        if let Some(prelude) = &config.typst.prelude {
+            let source = fs::read_to_string(dir.join(prelude))
+                .map_err(|e| format!("Failed to read prelude {prelude}: {e}"))?;
+            modules.push((PRELUDE, source));
+        }
+        let preamble = preamble(config, inputs.contains("labels"));
+        let header = if preamble.is_empty() {
+            String::new()
+        } else {
+            modules.push(("@didactic/preamble.typ", preamble));
+            PREAMBLE_IMPORT.to_string()
+        };
+
+        if let Some(packages) = &config.typst.packages
+            && !dir.join(packages).is_dir()
+        {
+            return Err(format!("Package directory {packages} does not exist").into());
+        }
+        let packages = PackageResolver {
+            roots: config
+                .typst
+                .packages
+                .iter()
+                .map(|p| dir.join(p))
+                .chain(package_roots.iter().cloned())
+                .collect()
+        };
+
+        let sources: Vec<PathBuf> = file_map
+            .logicals()
+            .filter(|l| l.extension().and_then(|s| s.to_str()) == Some("typ"))
+            .filter_map(|l| file_map.source_path(l))
+            .collect();
+        let build = |root: &Path| {
+            let pages = sources
+                .iter()
+                .filter_map(|s| s.strip_prefix(root).ok())
+                .map(Path::to_path_buf)
+                .collect();
+            TypstEngine::builder()
+                .add_file_resolver(PageResolver {
+                    root: root.to_path_buf(),
+                    pages,
+                    modules: modules.clone(),
+                    header: header.clone()
+                })
+                .add_file_resolver(packages.clone())
+                .add_file_resolver(ProjectFiles(
+                    FileSystemResolver::new(root.to_path_buf()).into_cached()
+                ))
+                .fonts(fonts.to_vec())
+                .build()
+        };
+
+        let links = config
+            .links
+            .iter()
+            .filter(|l| l.root == TypstRoot::Mount)
+            .map(|l| {
+                let root = dir.join(&l.path);
+                let engine = build(&root);
+                (root, engine)
+            })
+            .collect();
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            project: build(dir),
+            header,
+            prelude: config.typst.prelude.as_ref().map(PathBuf::from),
+            links
+        })
+    }
+
+    /// The engine compiling the page at `real`, relative to the project directory, along with the
+    /// path to compile relative to that engine's root.
+    pub fn for_source(&self, real: &Path) -> (&TypstEngine, PathBuf) {
+        let full = self.dir.join(real);
+        self.links
+            .iter()
+            .find_map(|(root, engine)| Some((engine, full.strip_prefix(root).ok()?.to_path_buf())))
+            .unwrap_or_else(|| (&self.project, real.to_path_buf()))
+    }
+
+    /// The page at `real` as its engine sees it, header included, so spans in its documents
+    /// resolve against it.
+    pub fn page_source(&self, real: &Path) -> Result<Source, Box<dyn Error>> {
+        let (_, path) = self.for_source(real);
+        let text = fs::read_to_string(self.dir.join(real))?;
+        let id = FileId::new(None, VirtualPath::new(&path));
+        Ok(Source::new(id, format!("{}{text}", self.header)))
+    }
+
+    /// Compiles the page at `real`, reporting errors as `path:line:column: message` with lines
+    /// counted in the file as written.
+    pub fn compile(&self, real: &Path, inputs: Dict) -> Result<HtmlDocument, Box<dyn Error>> {
+        let (engine, path) = self.for_source(real);
+        engine
+            .compile_with_input(path.to_str().unwrap(), inputs)
+            .output
+            .map_err(|e| {
+                let message = match e {
+                    TypstAsLibError::TypstSource(diagnostics) => diagnostics
+                        .iter()
+                        .map(|d| self.diagnostic(real, d))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    e => e.to_string()
+                };
+                format!("Compile failed for {}:\n{message}", real.display()).into()
+            })
+    }
+
+    fn diagnostic(&self, real: &Path, diagnostic: &SourceDiagnostic) -> String {
+        let mut out = format!(
+            "{}: {}",
+            self.position(real, diagnostic.span),
+            diagnostic.message
+        );
+        for hint in &diagnostic.hints {
+            out.push_str(&format!("\n  hint: {hint}"));
+        }
+        out
+    }
+
+    /// Where `span` points, as a path relative to the project directory followed by the line and
+    /// column when the file can be read back. Packages and bundled modules are shown by their
+    /// virtual path alone.
+    fn position(&self, real: &Path, span: Span) -> String {
+        let Some(id) = span.id() else {
+            return real.display().to_string();
+        };
+        let vpath = id.vpath();
+        if let Some(spec) = id.package() {
+            return format!("{spec}{}", vpath.as_rooted_path().display());
+        }
+        let (_, path) = self.for_source(real);
+        let (source, shown, offset) = if let Some(prelude) = &self.prelude
+            && vpath.as_rootless_path() == Path::new(PRELUDE)
+        {
+            let Ok(text) = fs::read_to_string(self.dir.join(prelude)) else {
+                return prelude.display().to_string();
+            };
+            (Source::new(id, text), prelude.clone(), 0)
+        } else if vpath.as_rootless_path().starts_with(MODULE_DIR) {
+            return vpath.as_rooted_path().display().to_string();
+        } else if id == FileId::new(None, VirtualPath::new(&path)) {
+            let Ok(source) = self.page_source(real) else {
+                return real.display().to_string();
+            };
+            (source, real.to_path_buf(), self.header.lines().count())
+        } else {
+            let root = self.root_of(real);
+            let Some(file) = vpath.resolve(root) else {
+                return vpath.as_rooted_path().display().to_string();
+            };
+            let shown = file.strip_prefix(&self.dir).unwrap_or(&file).to_path_buf();
+            let Ok(text) = fs::read_to_string(&file) else {
+                return shown.display().to_string();
+            };
+            (Source::new(id, text), shown, 0)
+        };
+        let position = source
+            .range(span)
+            .and_then(|range| source.lines().byte_to_line_column(range.start));
+        match position {
+            Some((line, column)) if line >= offset => {
+                format!("{}:{}:{}", shown.display(), line - offset + 1, column + 1)
+            }
+            Some(_) => format!("{} (preamble)", shown.display()),
+            None => shown.display().to_string()
+        }
+    }
+
+    /// The directory absolute paths in the page at `real` resolve from.
+    fn root_of(&self, real: &Path) -> &Path {
+        let full = self.dir.join(real);
+        self.links
+            .iter()
+            .map(|(root, _)| root.as_path())
+            .find(|root| full.starts_with(root))
+            .unwrap_or(&self.dir)
+    }
+}
+
+/// The source of `/@didactic/preamble.typ`, which re-exports the prelude's definitions and defines
+/// `didactic-rules` to apply the show rules the config asks for, followed by the prelude's `rules`.
+/// Empty when there are none.
+fn preamble(config: &Config, labels: bool) -> String {
+    let mathml = config.typst.math == MathOutput::Mathml;
+    let prelude = config.typst.prelude.is_some();
+    if !mathml && !prelude && !labels {
+        return String::new();
+    }
+    let mut lines = Vec::new();
+    if mathml {
+        lines.push("#import \"/@didactic/mathml.typ\" as didactic-mathml");
+    }
+    if labels {
+        lines.push("#import \"/@didactic/refs.typ\" as didactic-refs");
+    }
+    if prelude {
+        lines.push("#import \"/@didactic/prelude.typ\" as didactic-prelude");
+        lines.push("#import \"/@didactic/prelude.typ\": *");
+    }
+    lines.push("#let didactic-rules(body) = {");
+    if mathml {
+        lines.push("  show math.equation: didactic-mathml.mathml");
+    }
+    if labels {
+        lines.push("  show ref: didactic-refs.refs");
+    }
+    if prelude {
+        lines.push("  show: dictionary(didactic-prelude).at(\"rules\", default: it => it)");
+    }
+    lines.push("  body");
+    lines.push("}");
+    lines.push("");
+    lines.join("\n")
+}
+
+/// Serves the bundled modules along with the generated `preamble.typ` and the prelude, and
+/// prepends `header`, the preamble import, to every page. Anything else is left to the file system
+/// resolver.
+struct PageResolver {
+    root: PathBuf,
+    pages: HashSet<PathBuf>,
+    /// Generated modules by their rootless path
+    modules: Vec<(&'static str, String)>,
+    header: String
+}
+
+impl FileResolver for PageResolver {
+    fn resolve_binary(&self, id: FileId) -> FileResult<Cow<'_, Bytes>> {
+        Err(FileError::NotFound(id.vpath().as_rootless_path().into()))
+    }
+
+    fn resolve_source(&self, id: FileId) -> FileResult<Cow<'_, Source>> {
+        let path = id.vpath().as_rootless_path();
+        let not_found = || FileError::NotFound(path.into());
+        if id.package().is_some() {
+            return Err(not_found());
+        }
+        if let Some((_, text)) = self
+            .modules
+            .iter()
+            .find(|(module, _)| Path::new(module) == path)
+        {
+            return Ok(Cow::Owned(Source::new(id, text.clone())));
+        }
+        if let Ok(name) = path.strip_prefix(MODULE_DIR) {
+            let (_, text) = MODULES
+                .iter()
+                .find(|(module, _)| Path::new(module) == name)
+                .ok_or_else(not_found)?;
+            return Ok(Cow::Owned(Source::new(id, text.to_string())));
+        }
+        if self.header.is_empty() || !self.pages.contains(path) {
+            return Err(not_found());
+        }
+        let text =
+            fs::read_to_string(self.root.join(path)).map_err(|e| FileError::from_io(e, path))?;
+        Ok(Cow::Owned(Source::new(
+            id,
+            format!("{}{text}", self.header)
+        )))
+    }
+}
+
+/// Typst's local package directory followed by its download cache, where `typst` itself finds
+/// packages.
+pub fn package_roots() -> Vec<PathBuf> {
+    [dirs::data_dir(), dirs::cache_dir()]
+        .into_iter()
+        .flatten()
+        .map(|d| d.join(DEFAULT_PACKAGES_SUBDIR))
+        .collect()
+}
+
+/// Serves `@namespace/name:version` imports from the first of `roots` holding the package.
+/// Nothing is downloaded, so a package missing from every directory fails the page.
+#[derive(Clone)]
+struct PackageResolver {
+    roots: Vec<PathBuf>
+}
+
+impl PackageResolver {
+    fn path(&self, id: FileId) -> FileResult<PathBuf> {
+        let Some(spec) = id.package() else {
+            return Err(FileError::NotFound(id.vpath().as_rootless_path().into()));
+        };
+        let package_dir = self
+            .roots
+            .iter()
+            .map(|root| package_dir(root, spec))
+            .find(|dir| dir.is_dir())
+            .ok_or_else(|| FileError::Package(PackageError::NotFound(spec.clone())))?;
+        id.vpath()
+            .resolve(&package_dir)
+            .ok_or(FileError::AccessDenied)
+    }
+}
+
+impl FileResolver for PackageResolver {
+    fn resolve_binary(&self, id: FileId) -> FileResult<Cow<'_, Bytes>> {
+        let path = self.path(id)?;
+        let data = fs::read(&path).map_err(|e| FileError::from_io(e, &path))?;
+        Ok(Cow::Owned(Bytes::new(data)))
+    }
+
+    fn resolve_source(&self, id: FileId) -> FileResult<Cow<'_, Source>> {
+        let path = self.path(id)?;
+        let text = fs::read_to_string(&path).map_err(|e| FileError::from_io(e, &path))?;
+        Ok(Cow::Owned(Source::new(id, text)))
+    }
+}
+
+/// Reads files other than packages from the file system. It comes last, so its error is the one
+/// reported for a missing file, while packages are left to [`PackageResolver`].
+struct ProjectFiles(CachedFileResolver<FileSystemResolver>);
+
+impl ProjectFiles {
+    fn check(id: FileId) -> FileResult<()> {
+        match id.package() {
+            Some(spec) => Err(FileError::Package(PackageError::NotFound(spec.clone()))),
+            None => Ok(())
+        }
+    }
+}
+
+impl FileResolver for ProjectFiles {
+    fn resolve_binary(&self, id: FileId) -> FileResult<Cow<'_, Bytes>> {
+        Self::check(id)?;
+        self.0.resolve_binary(id)
+    }
+
+    fn resolve_source(&self, id: FileId) -> FileResult<Cow<'_, Source>> {
+        Self::check(id)?;
+        self.0.resolve_source(id)
+    }
+}
+
+fn package_dir(root: &Path, spec: &PackageSpec) -> PathBuf {
+    root.join(spec.namespace.as_str())
+        .join(spec.name.as_str())
+        .join(spec.version.to_string())
+}
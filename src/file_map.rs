@@ -3,35 +3,69 @@ use std::error::Error;
 use std::fs::{self};
 use std::path::{Path, PathBuf};
 
-use log::debug;
+use log::{debug, warn};
+
+use crate::ignored::{Ignored, MountFilter};
+
+/// How a directory is added to the map.
+#[derive(Debug, Default)]
+pub struct Mount {
+    /// Replace files already mapped to the same logical path instead of failing
+    pub shadow: bool,
+    pub filter: MountFilter
+}
 
 #[derive(Debug)]
 pub struct FileMap {
     entries: HashMap<PathBuf, PathBuf>,
-    resolver_base: Option<PathBuf>
+    resolver_base: Option<PathBuf>,
+    ignored: Ignored,
+    libraries: HashSet<PathBuf>
 }
 
 impl FileMap {
     pub fn with_resolver_base(base: impl Into<PathBuf>) -> Self {
         Self {
             entries: HashMap::new(),
-            resolver_base: Some(base.into())
+            resolver_base: Some(base.into()),
+            ignored: Ignored::none(),
+            libraries: HashSet::new()
         }
     }
 
+    /// Leaves out `libraries`, typst modules that are imported by pages but not pages themselves.
+    pub fn with_libraries(mut self, libraries: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.libraries.extend(libraries);
+        self
+    }
+
+    /// Leaves out files matching `ignored` from every directory added afterwards.
+    pub fn ignoring(mut self, ignored: Ignored) -> Self {
+        self.ignored = ignored;
+        self
+    }
+
+    pub fn ignored(&self) -> &Ignored {
+        &self.ignored
+    }
+
+    /// Maps the files in `dir` under `prefix`. A logical path that is already mapped is an error,
+    /// unless the mount shadows it, in which case the new file replaces it.
     pub fn add_directory(
         &mut self,
         dir: &Path,
-        prefix: Option<&Path>
+        prefix: Option<&Path>,
+        mount: &Mount
     ) -> Result<(), Box<dyn Error>> {
-        self.walk(dir, dir, prefix)
+        self.walk(dir, dir, prefix, mount)
     }
 
     fn walk(
         &mut self,
         dir: &Path,
         base: &Path,
-        prefix: Option<&Path>
+        prefix: Option<&Path>,
+        mount: &Mount
     ) -> Result<(), Box<dyn Error>> {
         debug!(
             "Walking dir: {:?}, with base {:?} and prefix {:?}",
@@ -40,14 +74,22 @@ impl FileMap {
         fs::read_dir(dir)?.try_for_each(|i| {
             let entry = i?;
             let real = entry.path();
+            if self.ignored.is_ignored(&real) {
+                debug!("Ignoring {:?}", real);
+                return Ok(());
+            }
             let relative = real.strip_prefix(base)?;
             let logical = match prefix {
                 Some(prefix) => prefix.join(relative),
                 None => relative.to_path_buf()
             };
             if real.is_dir() {
-                self.walk(&real, &real, Some(&logical))?;
-            } else {
+                if mount.filter.walks(&real) {
+                    self.walk(&real, &real, Some(&logical), mount)?;
+                }
+            } else if self.libraries.contains(&real) {
+                debug!("Skipping library {:?}", real);
+            } else if mount.filter.allows(&real) {
                 let stored_real = match &self.resolver_base {
                     Some(base) => real
                         .strip_prefix(base)
@@ -55,9 +97,26 @@ impl FileMap {
                         .unwrap_or_else(|_| real.clone()),
                     None => real.clone()
                 };
-                if stored_real.file_stem().unwrap() != "lib" {
-                    self.entries.insert(logical, stored_real);
+                if stored_real.file_name().unwrap() == "lib.typ" {
+                    warn!(
+                        "{} is rendered as a page, list it under [typst] libraries if it is only \
+                         imported",
+                        stored_real.display()
+                    );
                 }
+                if let Some(existing) = self.entries.get(&logical) {
+                    if !mount.shadow {
+                        return Err(format!(
+                            "{} and {} both map to {}",
+                            existing.display(),
+                            stored_real.display(),
+                            logical.display()
+                        )
+                        .into());
+                    }
+                    debug!("{:?} shadows {:?}", stored_real, existing);
+                }
+                self.entries.insert(logical, stored_real);
             }
             Ok::<(), Box<dyn Error>>(())
         })?;
@@ -85,6 +144,19 @@ impl FileMap {
             .collect()
     }
 
+    /// The real path of a logical path, joined onto the resolver base so it can be read directly.
+    pub fn source_path(&self, logical: &Path) -> Option<PathBuf> {
+        let real = self.entries.get(logical)?;
+        Some(match &self.resolver_base {
+            Some(base) => base.join(real),
+            None => real.clone()
+        })
+    }
+
+    pub fn logicals(&self) -> impl Iterator<Item = &PathBuf> {
+        self.entries.keys()
+    }
+
     pub fn contains(&self, logical: &Path) -> bool {
         self.entries.contains_key(logical)
     }
@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::fs::{self};
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+use typst::foundations::Bytes;
+use typst::text::{Font, FontStyle};
+
+/// Web fonts are copied here, relative to the output directory.
+const FONT_DIR: &str = "fonts";
+pub const FONT_CSS: &str = "fonts.css";
+const FONT_FILES: [&str; 4] = ["ttf", "otf", "ttc", "otc"];
+
+/// A font file along with the faces typst read from it.
+pub struct FontFile {
+    path: PathBuf,
+    faces: Vec<Font>
+}
+
+/// Reads every font in `dirs` and their subdirectories.
+pub fn load_fonts(dirs: &[PathBuf]) -> Result<Vec<FontFile>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for dir in dirs {
+        if !dir.is_dir() {
+            return Err(format!("Font directory {} does not exist", dir.display()).into());
+        }
+        collect_fonts(dir, &mut files)?;
+    }
+    Ok(files)
+}
+
+/// The bundled typst fonts followed by the faces of `files`.
+pub fn engine_fonts(files: &[FontFile]) -> Vec<Font> {
+    typst_assets::fonts()
+        .flat_map(|data| Font::iter(Bytes::new(data)))
+        .chain(files.iter().flat_map(|f| f.faces.iter().cloned()))
+        .collect()
+}
+
+/// Copies every single face font in `files` to `fonts/`, whether pages use it or not, and writes
+/// `fonts.css` with an `@font-face` rule for each. A `.woff2` next to a font with the same name is
+/// copied too and preferred. Nothing is written when there are no single face fonts.
+pub fn write_web_fonts(files: &[FontFile], out_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let font_dir = out_dir.join(FONT_DIR);
+    let mut css = String::new();
+    for file in files {
+        let [face] = file.faces.as_slice() else {
+            debug!("Skipping font collection {}", file.path.display());
+            continue;
+        };
+        let mut sources = Vec::new();
+        let woff2 = file.path.with_extension("woff2");
+        for (path, format) in [(&woff2, "woff2"), (&file.path, web_format(&file.path))] {
+            if !path.exists() {
+                continue;
+            }
+            let name = path.file_name().unwrap().to_str().unwrap();
+            fs::create_dir_all(&font_dir)?;
+            fs::copy(path, font_dir.join(name))?;
+            sources.push(format!(r#"url("/{FONT_DIR}/{name}") format("{format}")"#));
+        }
+
+        let info = face.info();
+        let style = match info.variant.style {
+            FontStyle::Normal => "normal",
+            FontStyle::Italic => "italic",
+            FontStyle::Oblique => "oblique"
+        };
+        css.push_str(&format!(
+            r#"@font-face {{
+  font-family: "{}";
+  src: {};
+  font-weight: {};
+  font-style: {};
+  font-display: swap;
+}}
+"#,
+            info.family,
+            sources.join(", "),
+            info.variant.weight.to_number(),
+            style
+        ));
+    }
+    if css.is_empty() {
+        warn!("No single face fonts to copy, so {FONT_CSS} is not written");
+        return Ok(());
+    }
+    fs::write(out_dir.join(FONT_CSS), css)?;
+    Ok(())
+}
+
+fn web_format(path: &Path) -> &'static str {
+    match path.extension().and_then(|s| s.to_str()) {
+        Some("otf") => "opentype",
+        _ => "truetype"
+    }
+}
+
+fn collect_fonts(dir: &Path, files: &mut Vec<FontFile>) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_fonts(&path, files)?;
+        } else if path
+            .extension()
+            .and_then(|s| s.to_str())
+            .is_some_and(|e| FONT_FILES.contains(&e.to_lowercase().as_str()))
+        {
+            let faces: Vec<Font> = Font::iter(Bytes::new(fs::read(&path)?)).collect();
+            if faces.is_empty() {
+                warn!("No usable fonts in {}", path.display());
+                continue;
+            }
+            debug!("Loaded {} faces from {}", faces.len(), path.display());
+            files.push(FontFile { path, faces });
+        }
+    }
+    Ok(())
+}
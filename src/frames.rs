@@ -0,0 +1,224 @@
+use std::error::Error;
+use std::fs::{self};
+use std::path::Path;
+
+use log::debug;
+use lol_html::{RewriteStrSettings, element, rewrite_str};
+use regex::Regex;
+use typst::introspection::{Location, Tag};
+use typst::math::EquationElem;
+use typst::syntax::{Source, Span};
+use typst_html::{HtmlElement, HtmlNode};
+
+use crate::build::{escape_xml, hash_bytes};
+use crate::config::FrameColors;
+
+/// Frames are written here, relative to the output directory.
+const FRAME_DIR: &str = "frames";
+
+/// Moves every inline `html.frame` SVG out to `frames/<hash>.svg` and references it with an
+/// `<img>`. Identical frames across the site share one file. `alts` holds the alt text of every
+/// frame in document order, as collected by [`frame_alts`].
+pub fn extract_frames(
+    html: &str,
+    alts: &[Option<String>],
+    out_dir: &Path
+) -> Result<String, Box<dyn Error>> {
+    let frames = find_frames(html);
+    if frames.is_empty() {
+        return Ok(html.to_string());
+    }
+    let aligned = frames.len() == alts.len();
+    if !aligned {
+        debug!(
+            "Found {} frames in the html but {} in the document, leaving alt text empty",
+            frames.len(),
+            alts.len()
+        );
+    }
+
+    let frame_dir = out_dir.join(FRAME_DIR);
+    fs::create_dir_all(&frame_dir)?;
+    let attr_re = Regex::new(r#"\s(class|style)="([^"]*)""#).unwrap();
+
+    let mut out = String::with_capacity(html.len());
+    let mut last = 0;
+    for (i, (start, end)) in frames.into_iter().enumerate() {
+        let svg = &html[start..end];
+        let hash = hash_bytes(svg.as_bytes());
+        let path = frame_dir.join(format!("{hash}.svg"));
+        if !path.exists() {
+            fs::write(&path, standalone_svg(svg))?;
+        }
+
+        let open_tag = &svg[..svg.find('>').unwrap_or(svg.len())];
+        let attrs: String = attr_re
+            .captures_iter(open_tag)
+            .map(|c| format!(r#" {}="{}""#, &c[1], &c[2]))
+            .collect();
+        let alt = match alts.get(i) {
+            Some(Some(alt)) if aligned => escape_xml(alt),
+            _ => String::new()
+        };
+
+        out.push_str(&html[last..start]);
+        out.push_str(&format!(
+            r#"<img src="/{FRAME_DIR}/{hash}.svg" alt="{alt}"{attrs}>"#
+        ));
+        last = end;
+    }
+    out.push_str(&html[last..]);
+    Ok(out)
+}
+
+/// Rewrites the `fill` and `stroke` colors inside frames so math follows the surrounding text
+/// color. Extracted frames are loaded as images and cannot inherit it, so they stay black.
+pub fn recolor_frames(html: &str, mode: FrameColors) -> Result<String, Box<dyn Error>> {
+    Ok(rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("svg.typst-frame *", |el| {
+                let mut style = String::new();
+                for property in ["fill", "stroke"] {
+                    let Some(hex) = el.get_attribute(property).and_then(|c| hex_color(&c)) else {
+                        continue;
+                    };
+                    let fallback = if hex == "000000" {
+                        "currentColor".to_string()
+                    } else {
+                        format!("#{hex}")
+                    };
+                    match mode {
+                        FrameColors::CurrentColor if hex == "000000" => {
+                            el.set_attribute(property, "currentColor")?;
+                        }
+                        FrameColors::CurrentColor => {}
+                        FrameColors::Variables => {
+                            el.remove_attribute(property);
+                            style.push_str(&format!("{property}:var(--frame-{hex},{fallback});"));
+                        }
+                    }
+                }
+                if !style.is_empty() {
+                    let existing = el.get_attribute("style").unwrap_or_default();
+                    let existing = existing.trim().trim_end_matches(';');
+                    if !existing.is_empty() {
+                        style.insert_str(0, &format!("{existing};"));
+                    }
+                    el.set_attribute("style", &style)?;
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        }
+    )?)
+}
+
+/// The alt text of every frame in a compiled page, in document order: the source of the equation
+/// the frame renders, or `None` for frames outside an equation or from another file. `source` is
+/// the page as it was compiled.
+pub fn frame_alts(root: &HtmlElement, source: &Source) -> Vec<Option<String>> {
+    /// `open` holds the location and span of the equations enclosing the current node.
+    fn walk(
+        element: &HtmlElement,
+        source: &Source,
+        open: &mut Vec<(Option<Location>, Span)>,
+        out: &mut Vec<Option<String>>
+    ) {
+        for node in &element.children {
+            match node {
+                HtmlNode::Tag(Tag::Start(elem, _)) if elem.is::<EquationElem>() => {
+                    open.push((elem.location(), elem.span()));
+                }
+                HtmlNode::Tag(Tag::End(location, ..))
+                    if open
+                        .last()
+                        .is_some_and(|(open, _)| *open == Some(*location)) =>
+                {
+                    open.pop();
+                }
+                HtmlNode::Frame(_) => {
+                    let alt = open.last().and_then(|(_, span)| {
+                        let text = source.text().get(source.range(*span)?)?;
+                        Some(text.trim_matches('$').trim().to_string())
+                    });
+                    out.push(alt);
+                }
+                HtmlNode::Element(child) => walk(child, source, open, out),
+                _ => {}
+            }
+        }
+    }
+    let mut alts = Vec::new();
+    walk(root, source, &mut Vec::new(), &mut alts);
+    alts
+}
+
+/// Removes the frames of the previous build, which may no longer be referenced.
+pub fn clear_frames(out_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let frame_dir = out_dir.join(FRAME_DIR);
+    if frame_dir.is_dir() {
+        fs::remove_dir_all(frame_dir)?;
+    }
+    Ok(())
+}
+
+/// Byte ranges of the outermost `<svg class="typst-frame">` elements.
+fn find_frames(html: &str) -> Vec<(usize, usize)> {
+    let frame_re = Regex::new(r#"<svg\b[^>]*\sclass="[^"]*\btypst-frame\b[^"]*"[^>]*>"#).unwrap();
+    let tag_re = Regex::new(r"</?svg\b").unwrap();
+
+    let mut frames = Vec::new();
+    let mut pos = 0;
+    while let Some(open) = frame_re.find_at(html, pos) {
+        let mut depth = 0;
+        let mut end = None;
+        for tag in tag_re.find_iter(&html[open.start()..]) {
+            if tag.as_str().starts_with("</") {
+                depth -= 1;
+                if depth == 0 {
+                    let close = open.start() + tag.start();
+                    end = html[close..].find('>').map(|i| close + i + 1);
+                    break;
+                }
+            } else {
+                depth += 1;
+            }
+        }
+        let Some(end) = end else {
+            break;
+        };
+        frames.push((open.start(), end));
+        pos = end;
+    }
+    frames
+}
+
+/// Inline SVG in HTML can leave out the namespaces a standalone file needs.
+fn standalone_svg(svg: &str) -> String {
+    let mut svg = svg.to_string();
+    if !svg[..svg.find('>').unwrap_or(0)].contains("xmlns=") {
+        svg = svg.replacen("<svg", r#"<svg xmlns="http://www.w3.org/2000/svg""#, 1);
+    }
+    if svg.contains("xlink:") && !svg.contains("xmlns:xlink") {
+        svg = svg.replacen(
+            "<svg",
+            r#"<svg xmlns:xlink="http://www.w3.org/1999/xlink""#,
+            1
+        );
+    }
+    svg
+}
+
+/// Normalizes `#rgb` and `#rrggbb` to lowercase `rrggbb`.
+pub fn hex_color(color: &str) -> Option<String> {
+    let hex = color.trim().strip_prefix('#')?.to_lowercase();
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        3 => Some(hex.chars().flat_map(|c| [c, c]).collect()),
+        6 => Some(hex),
+        _ => None
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use tera::{Error, Function, Result, Tera, Value, to_value};
+
+use crate::assets::AssetUrls;
+use crate::file_map::FileMap;
+use crate::meta::{PageMeta, find_page, page_url};
+
+/// Registers `asset_url`, `url_for`, `get_page` and `get_section` on `tera`. Unknown assets and
+/// pages are errors so broken references fail the build instead of rendering empty. The urls are
+/// marked safe so autoescaping does not turn their slashes into entities.
+pub fn register_functions(
+    tera: &mut Tera,
+    asset_hashes: &HashMap<String, String>,
+    asset_urls: &AssetUrls,
+    file_map: &FileMap,
+    page_metas: &[PageMeta]
+) {
+    // Assets without a fingerprint, like `favicon.ico` with hashed filenames, keep their path
+    let assets: HashMap<String, String> = asset_hashes
+        .keys()
+        .map(|k| {
+            (
+                k.clone(),
+                asset_urls.bust(k, "/").unwrap_or_else(|| k.clone())
+            )
+        })
+        .collect();
+    tera.register_function(
+        "asset_url",
+        SafeUrl(move |args: &HashMap<String, Value>| {
+            let path = string_arg(args, "asset_url", "path")?;
+            let key = format!("/{}", path.trim_start_matches('/'));
+            assets
+                .get(&key)
+                .map(|url| Value::String(url.clone()))
+                .ok_or_else(|| Error::msg(format!("asset_url: unknown asset {path}")))
+        })
+    );
+
+    let pages: HashMap<String, String> = file_map
+        .logicals()
+        .filter(|l| l.extension().and_then(|s| s.to_str()) == Some("typ"))
+        .map(|l| (l.to_str().unwrap().replace('\\', "/"), page_url(l)))
+        .collect();
+    tera.register_function(
+        "url_for",
+        SafeUrl(move |args: &HashMap<String, Value>| {
+            let path = string_arg(args, "url_for", "page")?;
+            pages
+                .get(path.trim_start_matches('/'))
+                .map(|url| Value::String(url.clone()))
+                .ok_or_else(|| Error::msg(format!("url_for: unknown page {path}")))
+        })
+    );
+
+    let metas = page_metas.to_vec();
+    tera.register_function("get_page", move |args: &HashMap<String, Value>| {
+        let path = string_arg(args, "get_page", "path")?;
+        let url = page_url(Path::new(path.trim_start_matches('/')));
+        find_page(&metas, &url)
+            .ok_or_else(|| Error::msg(format!("get_page: unknown page {path}")))
+            .and_then(|p| to_value(p).map_err(Error::from))
+    });
+
+    let metas = page_metas.to_vec();
+    tera.register_function("get_section", move |args: &HashMap<String, Value>| {
+        let path = string_arg(args, "get_section", "path")?;
+        let url = page_url(&Path::new(path.trim_matches('/')).join("index.typ"));
+        find_page(&metas, &url)
+            .ok_or_else(|| Error::msg(format!("get_section: unknown section {path}")))
+            .and_then(|p| to_value(p).map_err(Error::from))
+    });
+}
+
+/// A function returning a url built from known assets or pages, which needs no escaping.
+struct SafeUrl<F>(F);
+
+impl<F> Function for SafeUrl<F>
+where
+    F: Fn(&HashMap<String, Value>) -> Result<Value> + Sync + Send
+{
+    fn call(&self, args: &HashMap<String, Value>) -> Result<Value> {
+        (self.0)(args)
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+fn string_arg<'a>(args: &'a HashMap<String, Value>, function: &str, name: &str) -> Result<&'a str> {
+    args.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::msg(format!("{function} requires a `{name}` argument")))
+}
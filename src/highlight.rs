@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{self};
+use std::path::Path;
+
+use log::debug;
+use regex::{Captures, Regex};
+use syntect::highlighting::ThemeSet;
+use syntect::html::{ClassStyle, ClassedHTMLGenerator, css_for_theme_with_class_style};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::build::escape_xml;
+use crate::config::HighlightConfig;
+
+pub const STYLESHEET_NAME: &str = "highlight.css";
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Rules for line numbers and highlighted lines, appended to the theme.
+const LINE_CSS: &str = ".highlight .line-number {
+  display: inline-block;
+  min-width: 3ch;
+  margin-right: 1ch;
+  text-align: right;
+  opacity: 0.5;
+  user-select: none;
+}
+.highlight .line.highlighted {
+  display: inline-block;
+  width: 100%;
+  background: rgba(127, 127, 127, 0.2);
+}
+";
+
+pub struct Highlighter {
+    syntaxes: SyntaxSet
+}
+
+impl Highlighter {
+    pub fn load() -> Self {
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines()
+        }
+    }
+
+    /// Replaces every `<pre><code>` block with a known language with class based token spans,
+    /// one `<span class="line">` per line. `data-line-numbers` and `data-highlight="2,4-6"` on the
+    /// `<pre>` add line numbers and mark lines as highlighted. Blocks without a language, or one
+    /// that is not recognized, are left as typst exported them.
+    pub fn highlight(&self, html: &str) -> Result<String, Box<dyn Error>> {
+        let block_re =
+            Regex::new(r"(?s)<pre\b([^>]*)>\s*<code\b([^>]*)>(.*?)</code>\s*</pre>").unwrap();
+        let mut out = String::with_capacity(html.len());
+        let mut last = 0;
+        for caps in block_re.captures_iter(html) {
+            let (pre_attrs, code_attrs) = (&caps[1], &caps[2]);
+            let lang = attr(pre_attrs, "data-lang")
+                .or_else(|| attr(code_attrs, "data-lang"))
+                .or_else(|| {
+                    attr(code_attrs, "class").and_then(|c| {
+                        c.split_whitespace()
+                            .find_map(|c| c.strip_prefix("language-"))
+                            .map(str::to_string)
+                    })
+                });
+            let Some(syntax) = lang
+                .as_deref()
+                .and_then(|l| self.syntaxes.find_syntax_by_token(l))
+            else {
+                debug!("Leaving code block with language {lang:?} unhighlighted");
+                continue;
+            };
+
+            let text = unescape(&strip_tags(&caps[3]));
+            let mut generator =
+                ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntaxes, CLASS_STYLE);
+            for line in LinesWithEndings::from(&text) {
+                generator.parse_html_for_line_which_includes_newline(line)?;
+            }
+
+            let numbers = attr(pre_attrs, "data-line-numbers").is_some();
+            let highlighted = attr(pre_attrs, "data-highlight")
+                .map(|h| parse_lines(&h))
+                .unwrap_or_default();
+            let mut block = format!(
+                r#"<pre class="highlight" data-lang="{}"><code>"#,
+                escape_xml(lang.as_deref().unwrap_or_default())
+            );
+            for (i, line) in split_lines(&generator.finalize()).iter().enumerate() {
+                let n = i + 1;
+                let class = if highlighted.contains(&n) {
+                    "line highlighted"
+                } else {
+                    "line"
+                };
+                block.push_str(&format!(r#"<span class="{class}">"#));
+                if numbers {
+                    block.push_str(&format!(
+                        r#"<span class="line-number" aria-hidden="true">{n}</span>"#
+                    ));
+                }
+                block.push_str(line);
+                block.push_str("</span>\n");
+            }
+            block.truncate(block.trim_end().len());
+            block.push_str("</code></pre>");
+
+            let whole = caps.get(0).unwrap();
+            out.push_str(&html[last..whole.start()]);
+            out.push_str(&block);
+            last = whole.end();
+        }
+        out.push_str(&html[last..]);
+        Ok(out)
+    }
+}
+
+/// Writes the token colors of the configured themes to `highlight.css`, the dark theme behind a
+/// `prefers-color-scheme` media query.
+pub fn write_stylesheet(config: &HighlightConfig, out_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let themes = ThemeSet::load_defaults();
+    let theme_css = |name: &str| -> Result<String, Box<dyn Error>> {
+        let theme = themes
+            .themes
+            .get(name)
+            .ok_or_else(|| format!("Unknown highlight theme {name}"))?;
+        Ok(css_for_theme_with_class_style(theme, CLASS_STYLE)?)
+    };
+
+    let mut css = theme_css(&config.theme)?;
+    if let Some(dark) = &config.dark_theme {
+        css.push_str(&format!(
+            "@media (prefers-color-scheme: dark) {{\n{}}}\n",
+            theme_css(dark)?
+        ));
+    }
+    css.push_str(LINE_CSS);
+    fs::write(out_dir.join(STYLESHEET_NAME), css)?;
+    Ok(())
+}
+
+fn attr(attrs: &str, name: &str) -> Option<String> {
+    Regex::new(&format!(r#"(?:^|\s){name}(?:="([^"]*)")?(?:\s|$)"#))
+        .unwrap()
+        .captures(attrs)
+        .map(|c| c.get(1).map_or_else(String::new, |v| unescape(v.as_str())))
+}
+
+/// Parses `2,4-6` into the set of line numbers it covers.
+pub fn parse_lines(spec: &str) -> HashSet<usize> {
+    spec.split(',')
+        .filter_map(|part| match part.trim().split_once('-') {
+            Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+            None => {
+                let line = part.trim().parse().ok()?;
+                Some(line..=line)
+            }
+        })
+        .flatten()
+        .collect()
+}
+
+/// Splits highlighted html into lines, closing the spans still open at the end of a line and
+/// reopening them on the next.
+pub fn split_lines(html: &str) -> Vec<String> {
+    let tag_re = Regex::new(r"<span[^>]*>|</span>").unwrap();
+    let mut open: Vec<&str> = Vec::new();
+    let mut lines = Vec::new();
+    for line in html.strip_suffix('\n').unwrap_or(html).split('\n') {
+        let mut out = open.concat();
+        for tag in tag_re.find_iter(line) {
+            if tag.as_str() == "</span>" {
+                open.pop();
+            } else {
+                open.push(tag.as_str());
+            }
+        }
+        out.push_str(line);
+        out.push_str(&"</span>".repeat(open.len()));
+        lines.push(out);
+    }
+    lines
+}
+
+fn strip_tags(html: &str) -> String {
+    let html = Regex::new(r"<br\s*/?>").unwrap().replace_all(html, "\n");
+    Regex::new(r"<[^>]*>")
+        .unwrap()
+        .replace_all(&html, "")
+        .to_string()
+}
+
+fn unescape(text: &str) -> String {
+    let entity_re = Regex::new(r"&(#[0-9]+|#x[0-9a-fA-F]+|[a-z]+);").unwrap();
+    entity_re
+        .replace_all(text, |caps: &Captures| {
+            let entity = &caps[1];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16).ok())
+                    .unwrap_or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                    .and_then(char::from_u32)
+            };
+            c.map_or_else(|| caps[0].to_string(), String::from)
+        })
+        .to_string()
+}
@@ -0,0 +1,122 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// Gitignore style patterns in the project root, on top of `[build] ignore`.
+pub const IGNORE_FILE: &str = ".didacticignore";
+
+/// Files left out of both the file map and the copied assets. Dotfiles are ignored unless a
+/// whitelist pattern like `!.htaccess` matches them, except for `.well-known` which hosts expect
+/// to find in the output.
+#[derive(Debug)]
+pub struct Ignored {
+    rules: Gitignore
+}
+
+impl Ignored {
+    pub fn load(dir: &Path, patterns: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut builder = GitignoreBuilder::new(dir);
+        let ignore_file = dir.join(IGNORE_FILE);
+        if ignore_file.exists()
+            && let Some(e) = builder.add(&ignore_file)
+        {
+            return Err(e.into());
+        }
+        for pattern in patterns {
+            builder.add_line(None, pattern)?;
+        }
+        Ok(Self {
+            rules: builder.build()?
+        })
+    }
+
+    pub fn none() -> Self {
+        Self {
+            rules: Gitignore::empty()
+        }
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let matched = self.rules.matched(path, path.is_dir());
+        if matched.is_whitelist() {
+            return false;
+        }
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.') && n != ".well-known");
+        hidden || matched.is_ignore()
+    }
+}
+
+/// The `include` and `exclude` globs of a mount, matched against file paths relative to `root`,
+/// along with directories inside the mount that are not part of it.
+#[derive(Debug)]
+pub struct MountFilter {
+    root: PathBuf,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    skip: Vec<PathBuf>
+}
+
+impl MountFilter {
+    pub fn new(
+        root: &Path,
+        include: &[String],
+        exclude: &[String]
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            root: root.to_path_buf(),
+            include: (!include.is_empty())
+                .then(|| glob_set(include))
+                .transpose()?,
+            exclude: glob_set(exclude)?,
+            skip: Vec::new()
+        })
+    }
+
+    /// Leaves out everything under `dir`, like a link's own template directory, if it is inside
+    /// the mount.
+    pub fn skipping(mut self, dir: &Path) -> Self {
+        if let (Ok(root), Ok(dir)) = (self.root.canonicalize(), dir.canonicalize())
+            && let Ok(relative) = dir.strip_prefix(root)
+        {
+            self.skip.push(relative.to_path_buf());
+        }
+        self
+    }
+
+    /// Whether the directory at `path` is walked.
+    pub fn walks(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        !self.skip.iter().any(|s| relative.starts_with(s))
+    }
+
+    /// Whether the file at `path` belongs to the mount.
+    pub fn allows(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.include.as_ref().is_none_or(|i| i.is_match(relative))
+            && !self.exclude.is_match(relative)
+    }
+}
+
+impl Default for MountFilter {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::new(),
+            include: None,
+            exclude: GlobSet::empty(),
+            skip: Vec::new()
+        }
+    }
+}
+
+pub fn glob_set(patterns: &[String]) -> Result<GlobSet, Box<dyn Error>> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
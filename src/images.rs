@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self};
+use std::path::{Path, PathBuf};
+
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use log::debug;
+use lol_html::{RewriteStrSettings, element, rewrite_str};
+use regex::Regex;
+
+use crate::assets::{is_external, resolve};
+use crate::build::hash_bytes;
+use crate::config::{ImageConfig, ImageOutputFormat};
+
+/// Generated variants are copied here, relative to the output directory.
+const VARIANT_DIR: &str = "_img";
+const RASTER: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+/// Images whose intrinsic size is added to `<img>` tags.
+const SIZED: [&str; 7] = ["jpg", "jpeg", "png", "webp", "gif", "avif", "svg"];
+
+/// The resized variants of a source image, smallest first.
+#[derive(Debug)]
+pub struct ImageSet {
+    pub width: u32,
+    pub height: u32,
+    pub variants: Vec<(String, u32)>
+}
+
+/// Resizes and converts every raster image in `out_dir` to the configured widths and format.
+/// Variants are cached in `cache_dir` by the hash of the source so unchanged images are only
+/// decoded once. Re-encoding drops EXIF data, after applying its orientation.
+pub fn process_images(
+    out_dir: &Path,
+    cache_dir: &Path,
+    config: &ImageConfig
+) -> Result<HashMap<String, ImageSet>, Box<dyn Error>> {
+    let variant_dir = out_dir.join(VARIANT_DIR);
+    if variant_dir.exists() {
+        fs::remove_dir_all(&variant_dir)?;
+    }
+    fs::create_dir_all(&variant_dir)?;
+    fs::create_dir_all(cache_dir)?;
+
+    let mut sources = Vec::new();
+    collect_rasters(out_dir, &variant_dir, &mut sources)?;
+
+    let mut sets = HashMap::new();
+    for path in sources {
+        let hash = hash_bytes(&fs::read(&path)?);
+        let format = match config.format {
+            ImageOutputFormat::Webp => ImageFormat::WebP,
+            ImageOutputFormat::Avif => ImageFormat::Avif,
+            ImageOutputFormat::Original => ImageFormat::from_path(&path)?
+        };
+        let ext = format.extensions_str()[0];
+
+        let mut image = None;
+        let dims_path = cache_dir.join(format!("{hash}.dims"));
+        let (width, height) = match read_dims(&dims_path) {
+            Some(dims) => dims,
+            None => {
+                let decoded = decode(&path)?;
+                let dims = (decoded.width(), decoded.height());
+                fs::write(&dims_path, format!("{} {}", dims.0, dims.1))?;
+                image = Some(decoded);
+                dims
+            }
+        };
+
+        let mut widths: Vec<u32> = config
+            .widths
+            .iter()
+            .copied()
+            .filter(|w| *w < width)
+            .collect();
+        widths.push(width);
+        widths.sort_unstable();
+        widths.dedup();
+
+        let mut variants = Vec::new();
+        for w in widths {
+            let name = format!("{hash}-{w}.{ext}");
+            let cached = cache_dir.join(&name);
+            if !cached.exists() {
+                debug!("Resizing {} to {}px", path.display(), w);
+                if image.is_none() {
+                    image = Some(decode(&path)?);
+                }
+                let source = image.as_ref().unwrap();
+                let h = (u64::from(height) * u64::from(w) / u64::from(width)).max(1);
+                let resized = source.resize(w, u32::try_from(h)?, FilterType::Lanczos3);
+                let resized = if format == ImageFormat::Jpeg {
+                    DynamicImage::ImageRgb8(resized.to_rgb8())
+                } else {
+                    DynamicImage::ImageRgba8(resized.to_rgba8())
+                };
+                resized.save_with_format(&cached, format)?;
+            }
+            fs::copy(&cached, variant_dir.join(&name))?;
+            variants.push((format!("/{VARIANT_DIR}/{name}"), w));
+        }
+
+        let rel = path.strip_prefix(out_dir)?;
+        let url = format!("/{}", rel.to_str().unwrap().replace('\\', "/"));
+        sets.insert(
+            url,
+            ImageSet {
+                width,
+                height,
+                variants
+            }
+        );
+    }
+    Ok(sets)
+}
+
+/// Points `<img>` tags at processed images through `srcset` and `sizes`, adding the intrinsic
+/// `width` and `height` when neither is set. Images that already have a `srcset` are left alone.
+pub fn responsive_images(
+    html: &str,
+    base: &str,
+    sets: &HashMap<String, ImageSet>,
+    sizes: &str
+) -> Result<String, Box<dyn Error>> {
+    Ok(rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("img[src]", |el| {
+                if el.has_attribute("srcset") {
+                    return Ok(());
+                }
+                let src = el.get_attribute("src").unwrap();
+                let Some(set) = resolve(&src, base).and_then(|k| sets.get(&k)) else {
+                    return Ok(());
+                };
+                let srcset = set
+                    .variants
+                    .iter()
+                    .map(|(url, w)| format!("{url} {w}w"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                el.set_attribute("src", &set.variants.last().unwrap().0)?;
+                el.set_attribute("srcset", &srcset)?;
+                if !el.has_attribute("sizes") {
+                    el.set_attribute("sizes", sizes)?;
+                }
+                if !el.has_attribute("width") && !el.has_attribute("height") {
+                    el.set_attribute("width", &set.width.to_string())?;
+                    el.set_attribute("height", &set.height.to_string())?;
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        }
+    )?)
+}
+
+/// Reads the intrinsic size of every image in `out_dir`, keyed by url. Taken before assets are
+/// fingerprinted, so pages can look images up by the names they reference.
+pub fn image_sizes(out_dir: &Path) -> Result<HashMap<String, (u32, u32)>, Box<dyn Error>> {
+    fn walk(
+        dir: &Path,
+        base: &Path,
+        sizes: &mut HashMap<String, (u32, u32)>
+    ) -> Result<(), Box<dyn Error>> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(&path, base, sizes)?;
+            } else if path
+                .extension()
+                .and_then(|s| s.to_str())
+                .is_some_and(|e| SIZED.contains(&e.to_lowercase().as_str()))
+                && let Some(size) = intrinsic_size(&path)
+            {
+                let rel = path.strip_prefix(base)?;
+                sizes.insert(
+                    format!("/{}", rel.to_str().unwrap().replace('\\', "/")),
+                    size
+                );
+            }
+        }
+        Ok(())
+    }
+    let mut sizes = HashMap::new();
+    walk(out_dir, out_dir, &mut sizes)?;
+    Ok(sizes)
+}
+
+/// Gives every `<img>` pointing at a local image in `sizes` its intrinsic `width` and `height`,
+/// plus `loading="lazy"` and `decoding="async"`, unless the author already set them.
+pub fn size_images(
+    html: &str,
+    base: &str,
+    sizes: &HashMap<String, (u32, u32)>
+) -> Result<String, Box<dyn Error>> {
+    Ok(rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("img[src]", |el| {
+                let src = el.get_attribute("src").unwrap();
+                let Some(key) = resolve(&src, base).filter(|_| !is_external(&src)) else {
+                    return Ok(());
+                };
+                if !el.has_attribute("width")
+                    && !el.has_attribute("height")
+                    && let Some((width, height)) = sizes.get(&key)
+                {
+                    el.set_attribute("width", &width.to_string())?;
+                    el.set_attribute("height", &height.to_string())?;
+                }
+                if !el.has_attribute("loading") {
+                    el.set_attribute("loading", "lazy")?;
+                }
+                if !el.has_attribute("decoding") {
+                    el.set_attribute("decoding", "async")?;
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        }
+    )?)
+}
+
+/// Reads the displayed size of an image from its header, or from the root element of an SVG.
+fn intrinsic_size(path: &Path) -> Option<(u32, u32)> {
+    if path.extension().and_then(|s| s.to_str()) == Some("svg") {
+        return svg_size(&fs::read_to_string(path).ok()?);
+    }
+    let mut decoder = ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let (width, height) = decoder.dimensions();
+    match decoder.orientation().ok()? {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => Some((height, width)),
+        _ => Some((width, height))
+    }
+}
+
+pub fn svg_size(svg: &str) -> Option<(u32, u32)> {
+    let root = Regex::new(r"<svg\b[^>]*>").unwrap().find(svg)?.as_str();
+    let attr = |name: &str| {
+        Regex::new(&format!(r#"\s{name}="\s*([0-9.]+)(?:px)?\s*""#))
+            .unwrap()
+            .captures(root)
+            .and_then(|c| c[1].parse::<f64>().ok())
+    };
+    let (width, height) = match (attr("width"), attr("height")) {
+        (Some(width), Some(height)) => (width, height),
+        _ => {
+            let view_box = Regex::new(r#"\sviewBox="([^"]*)""#)
+                .unwrap()
+                .captures(root)?;
+            let values: Vec<f64> = view_box[1]
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            (*values.get(2)?, *values.get(3)?)
+        }
+    };
+    Some((width.round() as u32, height.round() as u32))
+}
+
+fn read_dims(path: &Path) -> Option<(u32, u32)> {
+    let dims = fs::read_to_string(path).ok()?;
+    let (width, height) = dims.split_once(' ')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+fn decode(path: &Path) -> Result<DynamicImage, Box<dyn Error>> {
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+fn collect_rasters(
+    dir: &Path,
+    skip: &Path,
+    paths: &mut Vec<PathBuf>
+) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path == skip {
+            continue;
+        }
+        if path.is_dir() {
+            collect_rasters(&path, skip, paths)?;
+        } else if path
+            .extension()
+            .and_then(|s| s.to_str())
+            .is_some_and(|e| RASTER.contains(&e.to_lowercase().as_str()))
+        {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
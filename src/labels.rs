@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::fs::{self};
+use std::path::PathBuf;
+
+use log::warn;
+use typst::foundations::{Dict, Str, Value};
+use typst::introspection::Tag;
+use typst::model::HeadingElem;
+use typst::syntax::{SyntaxKind, SyntaxNode};
+use typst_html::{HtmlAttr, HtmlElement, HtmlNode};
+
+use crate::file_map::FileMap;
+use crate::meta::page_url;
+
+/// Builds the site wide index of labelled headings, passed to typst as `sys.inputs.labels`. Each
+/// label maps to a dictionary with the `url` of the heading and its `text`. Labels are read from
+/// the page sources so every page can be compiled once with the full index.
+pub fn collect_labels(file_map: &FileMap) -> Result<Dict, Box<dyn Error>> {
+    let mut labels = Dict::new();
+    let mut logicals: Vec<&PathBuf> = file_map
+        .logicals()
+        .filter(|l| l.extension().and_then(|s| s.to_str()) == Some("typ"))
+        .collect();
+    logicals.sort();
+
+    for logical in logicals {
+        let Some(real) = file_map.source_path(logical) else {
+            continue;
+        };
+        let url = page_url(logical);
+        let mut headings = Vec::new();
+        labelled_headings(
+            &typst::syntax::parse(&fs::read_to_string(&real)?),
+            &mut headings
+        );
+        for (name, text) in headings {
+            if labels.contains(name.as_str()) {
+                warn!(
+                    "Label <{}> in {} is already defined, ignoring",
+                    name,
+                    logical.display()
+                );
+                continue;
+            }
+
+            let mut target = Dict::new();
+            target.insert("url".into(), Value::Str(format!("{url}#{name}").into()));
+            target.insert("text".into(), Value::Str(text.into()));
+            labels.insert(Str::from(name), Value::Dict(target));
+        }
+    }
+    Ok(labels)
+}
+
+/// Gives the element of every labelled heading its label as `id`, the way typst only does for
+/// headings linked from the same page, so the urls in `sys.inputs.labels` always resolve.
+pub fn label_heading_ids(root: &mut HtmlElement) {
+    let mut pending = None;
+    for node in root.children.make_mut() {
+        match node {
+            HtmlNode::Tag(Tag::Start(elem, _)) if elem.is::<HeadingElem>() => {
+                pending = elem
+                    .label()
+                    .map(|l| (elem.location(), l.resolve().as_str().to_string()));
+            }
+            HtmlNode::Tag(Tag::End(loc, ..))
+                if pending
+                    .as_ref()
+                    .is_some_and(|(start, _)| *start == Some(*loc)) =>
+            {
+                pending = None;
+            }
+            HtmlNode::Element(element) => {
+                let id = HtmlAttr::constant("id");
+                if let Some((_, label)) = pending.take()
+                    && element.attrs.get(id).is_none()
+                {
+                    element.attrs.push(id, label);
+                }
+                label_heading_ids(element);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Collects `= Heading <label>` pairs as the label name and the heading's plain text.
+fn labelled_headings(node: &SyntaxNode, out: &mut Vec<(String, String)>) {
+    let mut heading = None;
+    for child in node.children() {
+        match child.kind() {
+            SyntaxKind::Heading => heading = Some(plain_text(child)),
+            SyntaxKind::Space => {}
+            SyntaxKind::Label => {
+                if let Some(text) = heading.take() {
+                    let name = child.text().trim_start_matches('<').trim_end_matches('>');
+                    out.push((name.to_string(), text));
+                }
+            }
+            _ => {
+                heading = None;
+                labelled_headings(child, out);
+            }
+        }
+    }
+}
+
+fn plain_text(heading: &SyntaxNode) -> String {
+    fn walk(node: &SyntaxNode, out: &mut String) {
+        match node.kind() {
+            SyntaxKind::Text | SyntaxKind::Space | SyntaxKind::SmartQuote => {
+                out.push_str(node.text());
+            }
+            SyntaxKind::Escape => out.push_str(node.text().trim_start_matches('\\')),
+            SyntaxKind::Markup | SyntaxKind::Strong | SyntaxKind::Emph | SyntaxKind::Raw => {
+                node.children().for_each(|child| walk(child, out));
+            }
+            _ => {}
+        }
+    }
+    let mut text = String::new();
+    heading
+        .children()
+        .filter(|c| c.kind() == SyntaxKind::Markup)
+        .for_each(|c| walk(c, &mut text));
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
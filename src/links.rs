@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::path::{Component, Path, PathBuf};
+
+use regex::{Captures, Regex};
+
+use crate::file_map::FileMap;
+use crate::meta::page_url;
+
+/// Rewrites links to `.typ` files into the url the target page is rendered at. Relative links are
+/// resolved against the directory of `logical`, absolute ones against the logical root so links
+/// into `[[links]]` mounts go through their slug.
+pub fn resolve_typ_links(
+    html: &str,
+    logical: &Path,
+    file_map: &FileMap
+) -> Result<String, Box<dyn Error>> {
+    let re = Regex::new(r##"href="([^"#?]+\.typ)(#[^"]*)?""##).unwrap();
+    let base = logical.parent().unwrap_or(Path::new(""));
+    let mut unknown = Vec::new();
+
+    let html = re
+        .replace_all(html, |caps: &Captures| {
+            let href = &caps[1];
+            let fragment = caps.get(2).map_or("", |m| m.as_str());
+            if href.contains("://") || href.starts_with("//") {
+                return caps[0].to_string();
+            }
+            let target = match href.strip_prefix('/') {
+                Some(absolute) => normalize(Path::new(absolute)),
+                None => normalize(&base.join(href))
+            };
+            match target {
+                Some(target) if file_map.contains(&target) => {
+                    format!(r#"href="{}{fragment}""#, page_url(&target))
+                }
+                _ => {
+                    unknown.push(href.to_string());
+                    caps[0].to_string()
+                }
+            }
+        })
+        .to_string();
+
+    if unknown.is_empty() {
+        Ok(html)
+    } else {
+        Err(format!(
+            "{} links to unknown pages: {}",
+            logical.display(),
+            unknown.join(", ")
+        )
+        .into())
+    }
+}
+
+/// Lexically resolves `.` and `..`, returning `None` if the path escapes the logical root.
+pub fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(p) => out.push(p),
+            Component::ParentDir => {
+                if !out.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    Some(out)
+}
@@ -1,19 +1,37 @@
+mod assets;
 #[warn(clippy::pedantic, clippy::cargo)]
 mod build;
+mod check;
+mod compress;
 mod config;
+mod engine;
 mod file_map;
+mod fonts;
+mod frames;
+mod functions;
+mod highlight;
+mod ignored;
+mod images;
+mod labels;
+mod links;
 mod meta;
+mod minify;
 mod path_util;
 #[cfg(test)]
 mod test;
+mod toc;
+mod updated;
 
 use std::fs;
+use std::path::PathBuf;
+use std::process;
 
 use clap::{Parser, Subcommand};
 use env_logger::Env;
 use log::{error, info};
 
 use crate::build::run_build;
+use crate::check::run_check;
 use crate::path_util::DisplayablePathBuf;
 
 #[derive(Parser)]
@@ -27,14 +45,27 @@ struct Cli {
 enum Commands {
     /// Build the website
     Build {
-        /// Minify the html output
+        /// Minify the html output along with css and js assets
         #[arg(short, long)]
         minify: bool,
+        /// The build profile, used to pick settings like `[minify] profiles`
+        #[arg(short, long, default_value = "release")]
+        profile: String,
+        /// An extra directory of fonts to compile with, can be given more than once
+        #[arg(long = "font-path")]
+        font_paths: Vec<PathBuf>,
         /// The root directory to build
         #[arg(short, long, default_value_t = DisplayablePathBuf::from("./"))]
         dir: DisplayablePathBuf
     },
 
+    /// Checks the built website for broken internal links
+    Check {
+        /// The root directory of the build to check
+        #[arg(short, long, default_value_t = DisplayablePathBuf::from("./"))]
+        dir: DisplayablePathBuf
+    },
+
     /// Cleans the directory, ie deletes the dist folder
     Clean {
         /// The root directory of the build to clean
@@ -48,9 +79,21 @@ fn main() {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Build { minify, dir } => {
-            if let Err(e) = run_build(dir.0, minify) {
+        Commands::Build {
+            minify,
+            profile,
+            font_paths,
+            dir
+        } => {
+            if let Err(e) = run_build(&dir.0, minify, &profile, &font_paths) {
                 error!("Build failed: {}", e);
+                process::exit(1);
+            }
+        }
+        Commands::Check { dir } => {
+            if let Err(e) = run_check(&dir) {
+                error!("Check failed: {}", e);
+                process::exit(1);
             }
         }
         Commands::Clean { dir } => {
@@ -0,0 +1,117 @@
+// Converts typst math into MathML for html export. Equations using anything this does not know how
+// to convert are rendered as an html.frame instead.
+
+#let _sequence = [a *b*].func()
+#let _space = [ ].func()
+#let _styled = {
+  set text(red)
+  [a]
+}.func()
+
+#let _scripts = ("t", "b", "tl", "bl", "tr", "br")
+
+#let _supported(it) = {
+  if it == none or type(it) == str {
+    return true
+  }
+  let f = it.func()
+  if f == _sequence {
+    it.children.all(_supported)
+  } else if f == _space or f == h or f == math.op or f == math.primes or it.has("text") {
+    true
+  } else if f == math.frac {
+    _supported(it.num) and _supported(it.denom)
+  } else if f == math.attach {
+    // Only one script per side after the base converts, anything else needs mmultiscripts
+    let has(s) = it.at(s, default: none) != none
+    let convertible = not (has("tl") or has("bl") or has("t") and has("tr") or has("b") and has("br"))
+    convertible and _supported(it.base) and _scripts.all(s => _supported(it.at(s, default: none)))
+  } else if f == math.root {
+    _supported(it.index) and _supported(it.radicand)
+  } else if f == _styled {
+    _supported(it.child)
+  } else if f in (math.lr, math.class, math.limits, math.scripts) {
+    _supported(it.body)
+  } else {
+    false
+  }
+}
+
+#let _plain(it) = {
+  if type(it) == str {
+    it
+  } else if it.has("text") {
+    _plain(it.text)
+  } else if it.has("children") {
+    it.children.map(_plain).join()
+  } else {
+    ""
+  }
+}
+
+#let _token(s) = {
+  if s.match(regex("^[0-9.,]+$")) != none {
+    html.elem("mn", s)
+  } else if s.match(regex("^\\p{L}+$")) != none {
+    html.elem("mi", s)
+  } else {
+    html.elem("mo", s)
+  }
+}
+
+#let _convert(it) = {
+  if it == none {
+    return none
+  }
+  if type(it) == str {
+    return _token(it)
+  }
+  let row(body) = html.elem("mrow", _convert(body))
+  let f = it.func()
+  if f == _sequence {
+    it.children.map(_convert).join()
+  } else if f == _space or f == h {
+    none
+  } else if f == math.op {
+    html.elem("mi", _plain(it.text))
+  } else if f == math.primes {
+    html.elem("mo", "′" * it.count)
+  } else if it.has("text") {
+    _convert(it.text)
+  } else if f == math.frac {
+    html.elem("mfrac", row(it.num) + row(it.denom))
+  } else if f == math.attach {
+    let sup = it.at("t", default: it.at("tr", default: none))
+    let sub = it.at("b", default: it.at("br", default: none))
+    if sup != none and sub != none {
+      html.elem("msubsup", row(it.base) + row(sub) + row(sup))
+    } else if sup != none {
+      html.elem("msup", row(it.base) + row(sup))
+    } else if sub != none {
+      html.elem("msub", row(it.base) + row(sub))
+    } else {
+      _convert(it.base)
+    }
+  } else if f == math.root {
+    if it.index == none {
+      html.elem("msqrt", _convert(it.radicand))
+    } else {
+      html.elem("mroot", row(it.radicand) + row(it.index))
+    }
+  } else if f == _styled {
+    _convert(it.child)
+  } else {
+    row(it.body)
+  }
+}
+
+#let mathml(it) = context {
+  if target() != "html" {
+    it
+  } else if _supported(it.body) {
+    let display = if it.block { "block" } else { "inline" }
+    html.elem("math", attrs: (display: display), _convert(it.body))
+  } else {
+    html.frame(it)
+  }
+}
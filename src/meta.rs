@@ -4,27 +4,66 @@ use std::path::{Path, PathBuf};
 
 use log::{debug, warn};
 use typst::foundations::{Dict, Str, Value};
-use typst_as_lib::TypstEngine;
 use typst_html::HtmlDocument;
 
+use crate::config::Config;
+use crate::engine::Engines;
 use crate::file_map::FileMap;
+use crate::updated::{LastModified, epoch_secs, format_rfc2822};
 
-#[derive(serde::Serialize, Debug)]
+pub const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+pub const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"
+];
+
+#[derive(serde::Serialize, Debug, Clone)]
 pub struct PageMeta {
     pub title: String,
     pub url: String,
     pub section: String,
     pub date: Option<String>,
-    pub children: Vec<PageMeta>
+    pub updated: Option<String>,
+    pub children: Vec<PageMeta>,
+    /// `date` and `updated` as seconds since the epoch, for the sitemap and Atom feed
+    #[serde(skip)]
+    pub published: Option<u64>,
+    #[serde(skip)]
+    pub modified: Option<u64>
+}
+
+/// Everything needed to compile a page. `inputs` are passed to every page as `sys.inputs`
+/// alongside `target`, and the page's own `path` and `url`.
+pub struct PageCompiler<'a> {
+    pub engines: &'a Engines,
+    pub file_map: &'a FileMap,
+    pub last_modified: Option<&'a LastModified>,
+    pub inputs: Dict
+}
+
+impl PageCompiler<'_> {
+    pub fn compile(&self, logical: &Path) -> Result<HtmlDocument, Box<dyn Error>> {
+        let real = self
+            .file_map
+            .get_real(logical)
+            .ok_or_else(|| format!("{} is not mapped", logical.display()))?;
+        let mut inputs = self.inputs.clone();
+        inputs.insert("target".into(), Value::Str(Str::from("html")));
+        inputs.insert(
+            "path".into(),
+            Value::Str(Str::from(logical.to_str().unwrap().replace('\\', "/")))
+        );
+        inputs.insert("url".into(), Value::Str(Str::from(page_url(logical))));
+        self.engines.compile(real, inputs)
+    }
 }
 
 pub fn collect_page_meta(
     prefix: &Path,
-    file_map: &FileMap,
-    engine: &TypstEngine,
+    compiler: &PageCompiler,
     cache: &mut HashMap<PathBuf, HtmlDocument>,
     is_root: bool
 ) -> Result<Vec<PageMeta>, Box<dyn Error>> {
+    let file_map = compiler.file_map;
     let mut items = Vec::new();
 
     for dir in file_map.subdirs_at(prefix) {
@@ -32,27 +71,16 @@ pub fn collect_page_meta(
         if file_map.contains(&index) {
             let real = file_map.get_real(&index).unwrap();
             debug!("Compiling index path {}", real.display());
-            let mut inputs = Dict::new();
-            inputs.insert("target".into(), Value::Str(Str::from("html")));
-            let doc: HtmlDocument = engine
-                .compile_with_input(real.to_str().unwrap(), inputs)
-                .output
-                .map_err(|e| format!("Compile failed: {:?}", e))?;
+            let doc = compiler.compile(&index)?;
             let stem = dir.file_stem().unwrap().to_string_lossy();
-            let url = format!(
-                "/{}",
-                index
-                    .parent()
-                    .expect("Unable to get parent")
-                    .to_str()
-                    .unwrap()
-                    .replace('\\', "/")
-            );
+            let url = page_url(&index);
 
-            let children = collect_page_meta(&dir, file_map, engine, cache, false)?;
+            let children = collect_page_meta(&dir, compiler, cache, false)?;
 
             let title = extract_title_from_doc(&doc, &stem.to_uppercase());
             let date = extract_date(&doc);
+            let published = extract_timestamp(&doc);
+            let modified = compiler.last_modified.and_then(|l| l.get(real));
             cache.insert(index, doc);
 
             items.push(PageMeta {
@@ -60,7 +88,10 @@ pub fn collect_page_meta(
                 url,
                 section: stem.to_string(),
                 date,
-                children
+                updated: modified.map(format_rfc2822),
+                children,
+                published,
+                modified
             });
         } else {
             warn!(
@@ -77,30 +108,23 @@ pub fn collect_page_meta(
 
         let real = file_map.get_real(logical).unwrap();
         debug!("Compiling path {}", real.display());
-        let mut inputs = Dict::new();
-        inputs.insert("target".into(), Value::Str(Str::from("html")));
-        let doc: HtmlDocument = engine
-            .compile_with_input(real.to_str().unwrap(), inputs)
-            .output
-            .map_err(|e| format!("{:?}", e))?;
-        let url = format!(
-            "/{}",
-            logical
-                .with_extension("html")
-                .to_str()
-                .unwrap()
-                .replace('\\', "/")
-        );
+        let doc = compiler.compile(logical)?;
+        let url = page_url(logical);
         let stem = logical.file_stem().unwrap().to_string_lossy();
         let title = extract_title_from_doc(&doc, &stem.to_uppercase());
         let date = extract_date(&doc);
+        let published = extract_timestamp(&doc);
+        let modified = compiler.last_modified.and_then(|l| l.get(real));
         cache.insert(logical.clone(), doc);
         items.push(PageMeta {
             title,
             url,
             section: String::new(),
             date,
-            children: vec![]
+            updated: modified.map(format_rfc2822),
+            children: vec![],
+            published,
+            modified
         });
     }
 
@@ -108,6 +132,80 @@ pub fn collect_page_meta(
     Ok(items)
 }
 
+/// Inputs shared by every page: the `site` table, the build `profile` and the user's
+/// `[typst.inputs]`, which can not replace the built in inputs.
+pub fn site_inputs(config: &Config, profile: &str) -> Dict {
+    let mut inputs: Dict = config
+        .typst
+        .inputs
+        .iter()
+        .map(|(k, v)| (Str::from(k.as_str()), toml_value(v)))
+        .collect();
+    let site = &config.site;
+    let site: Dict = [
+        ("title", &site.title),
+        ("author", &site.author),
+        ("base_url", &site.base_url),
+        ("description", &site.description)
+    ]
+    .into_iter()
+    .map(|(k, v)| (Str::from(k), Value::Str(Str::from(v.as_str()))))
+    .collect();
+    inputs.insert("site".into(), Value::Dict(site));
+    inputs.insert("profile".into(), Value::Str(Str::from(profile)));
+    inputs
+}
+
+pub fn toml_value(value: &toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::Str(Str::from(s.as_str())),
+        toml::Value::Integer(i) => Value::Int(*i),
+        toml::Value::Float(f) => Value::Float(*f),
+        toml::Value::Boolean(b) => Value::Bool(*b),
+        toml::Value::Datetime(d) => Value::Str(Str::from(d.to_string())),
+        toml::Value::Array(a) => Value::Array(a.iter().map(toml_value).collect()),
+        toml::Value::Table(t) => Value::Dict(
+            t.iter()
+                .map(|(k, v)| (Str::from(k.as_str()), toml_value(v)))
+                .collect()
+        )
+    }
+}
+
+/// The url a logical `.typ` path is linked under. Section indexes are linked by their directory.
+pub fn page_url(logical: &Path) -> String {
+    let target = match logical.parent() {
+        Some(parent)
+            if logical.file_stem().unwrap() == "index" && parent.components().count() > 0 =>
+        {
+            parent.to_path_buf()
+        }
+        _ => logical.with_extension("html")
+    };
+    format!("/{}", target.to_str().unwrap().replace('\\', "/"))
+}
+
+/// Finds the page with the given url among the collected metadata.
+pub fn find_page<'a>(pages: &'a [PageMeta], url: &str) -> Option<&'a PageMeta> {
+    pages.iter().find_map(|p| {
+        if p.url == url {
+            Some(p)
+        } else {
+            find_page(&p.children, url)
+        }
+    })
+}
+
+pub fn find_page_mut<'a>(pages: &'a mut [PageMeta], url: &str) -> Option<&'a mut PageMeta> {
+    pages.iter_mut().find_map(|p| {
+        if p.url == url {
+            Some(p)
+        } else {
+            find_page_mut(&mut p.children, url)
+        }
+    })
+}
+
 fn sort_meta(items: &mut [PageMeta]) {
     items.sort_by(|a, b| match (a.url.as_str(), b.url.as_str()) {
         (u, _) if u.ends_with("/index.html") => std::cmp::Ordering::Less,
@@ -123,21 +221,26 @@ fn sort_meta(items: &mut [PageMeta]) {
 
 fn extract_date(doc: &HtmlDocument) -> Option<String> {
     doc.info.date.custom().flatten().map(|d| {
-        let days = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
-        let months = [
-            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"
-        ];
         let weekday = d.weekday().unwrap() as usize;
         format!(
             "{}, {:02} {} {} 00:00:00 +0000",
-            days[weekday],
+            WEEKDAYS[weekday],
             d.day().unwrap(),
-            months[d.month().unwrap() as usize - 1],
+            MONTHS[d.month().unwrap() as usize - 1],
             d.year().unwrap()
         )
     })
 }
 
+fn extract_timestamp(doc: &HtmlDocument) -> Option<u64> {
+    let d = doc.info.date.custom().flatten()?;
+    epoch_secs(
+        i64::from(d.year()?),
+        u64::from(d.month()?),
+        u64::from(d.day()?)
+    )
+}
+
 fn extract_title_from_doc(doc: &HtmlDocument, default: &str) -> String {
     doc.info
         .title
@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::fs::{self};
+use std::path::Path;
+
+use globset::GlobSet;
+use log::debug;
+use minify_html::Cfg;
+
+use crate::config::MinifyConfig;
+use crate::ignored::glob_set;
+
+/// Minifier settings for rendered pages, including their inline `<style>` and `<script>`, along
+/// with the `exclude` globs of output paths left alone.
+pub struct Minifier {
+    pub cfg: Cfg,
+    exclude: GlobSet
+}
+
+impl Minifier {
+    pub fn new(config: &MinifyConfig) -> Result<Self, Box<dyn Error>> {
+        let mut cfg = Cfg::new();
+        cfg.keep_comments = config.keep_comments;
+        cfg.keep_closing_tags = config.keep_closing_tags;
+        cfg.keep_ssi_comments = config.keep_ssi_comments;
+        cfg.minify_css = config.minify_css;
+        cfg.minify_js = config.minify_js;
+        Ok(Self {
+            cfg,
+            exclude: glob_set(&config.exclude)?
+        })
+    }
+
+    /// Whether the file at `relative`, a path inside the output directory, may be minified.
+    pub fn allows(&self, relative: &Path) -> bool {
+        !self.exclude.is_match(relative)
+    }
+}
+
+/// Minifies every stylesheet and script in `out_dir`, as far as `minifier` allows. Files named like
+/// `*.min.js` are assumed to be minified already and skipped.
+pub fn minify_assets(out_dir: &Path, minifier: &Minifier) -> Result<(), Box<dyn Error>> {
+    minify_dir(out_dir, out_dir, minifier)
+}
+
+fn minify_dir(dir: &Path, out_dir: &Path, minifier: &Minifier) -> Result<(), Box<dyn Error>> {
+    let cfg = &minifier.cfg;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            minify_dir(&path, out_dir, minifier)?;
+            continue;
+        }
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let tag = match path.extension().and_then(|s| s.to_str()) {
+            Some("css") if cfg.minify_css => "style",
+            Some("js" | "mjs") if cfg.minify_js => "script",
+            _ => continue
+        };
+        if name.contains(".min.") || !minifier.allows(path.strip_prefix(out_dir)?) {
+            continue;
+        }
+        debug!("Minifying {}", path.display());
+        let source = fs::read_to_string(&path)?;
+        if let Some(minified) = minify_embedded(&source, tag, cfg) {
+            fs::write(&path, minified)?;
+        }
+    }
+    Ok(())
+}
+
+/// minify-html only minifies CSS and JS inside a page, so the file is wrapped in a `<style>` or
+/// `<script>` and unwrapped again. Files that would close the wrapper early are left alone.
+fn minify_embedded(source: &str, tag: &str, cfg: &Cfg) -> Option<String> {
+    let close = format!("</{tag}");
+    if source.to_lowercase().contains(&close) {
+        return None;
+    }
+    let wrapped = format!("<{tag}>{source}{close}>");
+    let minified = String::from_utf8(minify_html::minify(wrapped.as_bytes(), cfg)).ok()?;
+    minified
+        .strip_prefix(&format!("<{tag}>"))?
+        .strip_suffix(&format!("{close}>"))
+        .map(str::to_string)
+}
@@ -0,0 +1,12 @@
+// Links references to labels on other pages, which didactic passes in as sys.inputs.labels.
+// References to labels in the same page are left to typst.
+
+#let refs(it) = {
+  let labels = sys.inputs.at("labels", default: (:))
+  if it.element == none and str(it.target) in labels {
+    let target = labels.at(str(it.target))
+    link(target.url, target.text)
+  } else {
+    it
+  }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use env_logger::Env;
 
@@ -10,17 +10,1070 @@ fn run_build() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::Builder::from_env(Env::default().default_filter_or("didactic=debug,warn"))
         .is_test(true)
         .init();
-    crate::build::run_build(test_dir(), false)
+    crate::build::run_build(&test_dir(), false, "release", &[])
 }
 
 fn built_file(path: &str) -> PathBuf {
     test_dir().join("dist").join(path)
 }
 
+/// Writes a fresh project to the temp directory, configured like the test project plus
+/// `extra_toml`, holding `files` by their path relative to the project root.
+fn project_dir(name: &str, extra_toml: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("didactic-{name}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("content")).unwrap();
+    let config = std::fs::read_to_string(test_dir().join("didactic.toml")).unwrap() + extra_toml;
+    std::fs::write(dir.join("didactic.toml"), config).unwrap();
+    for (path, text) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+    dir
+}
+
+/// Like [`project_dir`], along with the project's config and file map.
+fn project(
+    name: &str,
+    extra_toml: &str,
+    files: &[(&str, &str)]
+) -> (PathBuf, crate::config::Config, crate::file_map::FileMap) {
+    let dir = project_dir(name, extra_toml, files);
+    let config = crate::build::read_config(&dir).unwrap();
+    let file_map = crate::build::build_file_map(&dir, &config).unwrap();
+    (dir, config, file_map)
+}
+
+fn engines(
+    dir: &Path,
+    config: &crate::config::Config,
+    file_map: &crate::file_map::FileMap
+) -> crate::engine::Engines {
+    crate::engine::Engines::build(
+        dir,
+        config,
+        file_map,
+        &crate::fonts::engine_fonts(&[]),
+        &typst::foundations::Dict::new(),
+        &[]
+    )
+    .unwrap()
+}
+
+/// The source file of the page at `logical`.
+fn real<'a>(file_map: &'a crate::file_map::FileMap, logical: &str) -> &'a Path {
+    file_map.get_real(Path::new(logical)).unwrap()
+}
+
 #[test]
 fn test_build() {
     assert!(run_build().is_ok(), "Build should succeed");
     assert!(built_file("index.html").exists(), "index.html should exist");
     assert!(built_file("style.css").exists(), "style.css should exist");
     assert!(built_file("rss.xml").exists(), "rss.xml should exist");
+    assert!(built_file("atom.xml").exists(), "atom.xml should exist");
+    assert!(
+        built_file("sitemap.xml").exists(),
+        "sitemap.xml should exist"
+    );
+    let index = std::fs::read_to_string(built_file("index.html")).unwrap();
+    assert!(
+        index.contains(r#"<h2 id="overview">"#),
+        "Labelled headings should use their label as id"
+    );
+    assert!(
+        index.contains("/style.css?v="),
+        "Stylesheet link should be cache busted"
+    );
+}
+
+#[test]
+fn test_toc() {
+    let (html, toc) =
+        crate::toc::build_toc("<h1>Title</h1><h2>Intro</h2><h3>Detail</h3><h2>Intro</h2>").unwrap();
+    assert!(
+        html.contains(r#"<h1 id="title">"#),
+        "Title should get an id"
+    );
+    assert_eq!(toc.len(), 2, "Only h2 headings should be top level");
+    assert_eq!(toc[0].children[0].id, "detail");
+    assert_eq!(toc[1].id, "intro-1", "Duplicate ids should be numbered");
+
+    let (html, toc) = crate::toc::build_toc(
+        r#"<h2 title="a > b">Nested <em>markup</em></h2><h2 id="kept">Kept</h2>"#
+    )
+    .unwrap();
+    assert!(
+        html.contains(r#"<h2 title="a > b" id="nested-markup">Nested <em>markup</em></h2>"#),
+        "Attributes containing > and nested markup should survive"
+    );
+    assert_eq!(toc[0].text, "Nested markup");
+    assert_eq!(toc[1].id, "kept", "Existing ids should be kept");
+}
+
+#[test]
+fn test_dates() {
+    use crate::updated::{epoch_secs, format_rfc2822, format_rfc3339};
+
+    assert_eq!(format_rfc2822(0), "Thu, 01 Jan 1970 00:00:00 +0000");
+    assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+    assert_eq!(
+        format_rfc2822(951_868_799),
+        "Tue, 29 Feb 2000 23:59:59 +0000",
+        "2000 is a leap year"
+    );
+    assert_eq!(format_rfc3339(1_709_164_800), "2024-02-29T00:00:00Z");
+    assert_eq!(
+        format_rfc3339(4_107_542_399),
+        "2100-02-28T23:59:59Z",
+        "2100 is not a leap year"
+    );
+    assert_eq!(
+        format_rfc2822(4_107_542_400),
+        "Mon, 01 Mar 2100 00:00:00 +0000"
+    );
+
+    assert_eq!(epoch_secs(1970, 1, 1), Some(0));
+    assert_eq!(epoch_secs(2000, 2, 29), Some(951_782_400));
+    assert_eq!(epoch_secs(2100, 3, 1), Some(4_107_542_400));
+    assert_eq!(
+        epoch_secs(1969, 12, 31),
+        None,
+        "Dates before 1970 have no timestamp"
+    );
+}
+
+#[test]
+fn test_labels() {
+    let config = crate::build::read_config(&test_dir()).unwrap();
+    let file_map = crate::build::build_file_map(&test_dir(), &config).unwrap();
+    let labels = crate::labels::collect_labels(&file_map).unwrap();
+    let typst::foundations::Value::Dict(overview) = labels.get("overview").unwrap() else {
+        panic!("Labels should map to dictionaries");
+    };
+    assert_eq!(
+        overview
+            .get("url")
+            .unwrap()
+            .clone()
+            .cast::<String>()
+            .unwrap(),
+        "/index.html#overview"
+    );
+    assert_eq!(
+        overview
+            .get("text")
+            .unwrap()
+            .clone()
+            .cast::<String>()
+            .unwrap(),
+        "Overview of the site"
+    );
+}
+
+#[test]
+fn test_cross_page_refs() {
+    let dir = project_dir(
+        "cross-page-refs",
+        "",
+        &[
+            ("templates/index.html", "{{ content | safe }}"),
+            ("content/index.typ", "= Intro <intro>\n"),
+            ("content/other.typ", "See @intro.\n")
+        ]
+    );
+    crate::build::run_build(&dir, false, "release", &[]).unwrap();
+    let other = std::fs::read_to_string(dir.join("dist/other.html")).unwrap();
+    assert!(
+        other.contains(r#"href="/index.html#intro""#),
+        "References to other pages should link to the label: {other}"
+    );
+}
+
+#[test]
+fn test_check_links() {
+    let out_dir = std::env::temp_dir().join("didactic-check-links");
+    let _ = std::fs::remove_dir_all(&out_dir);
+    std::fs::create_dir_all(out_dir.join("notes")).unwrap();
+    std::fs::write(out_dir.join("my notes.html"), r#"<h2 id="top">Top</h2>"#).unwrap();
+    std::fs::write(out_dir.join("a.png"), "").unwrap();
+    std::fs::write(
+        out_dir.join("notes/index.html"),
+        r#"<a href="../my%20notes.html#top">Notes</a><img srcset="/a.png 1x, ../a.png 2x">"#
+    )
+    .unwrap();
+    let file_map = crate::file_map::FileMap::with_resolver_base(&out_dir);
+    assert!(
+        crate::check::check_links(&out_dir, &file_map).is_ok(),
+        "Escaped paths and srcset candidates should resolve"
+    );
+
+    std::fs::write(
+        out_dir.join("index.html"),
+        r#"<a href="/my%20notes.html#bottom">Missing id</a><img srcset="/b.png 2x">"#
+    )
+    .unwrap();
+    let result = crate::check::check_links(&out_dir, &file_map);
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Found 2 broken links",
+        "A missing fragment and a missing srcset candidate should be reported"
+    );
+}
+
+#[test]
+fn test_fingerprint_assets() {
+    use crate::assets::{
+        AssetUrls, MANIFEST_NAME, bust_html, fingerprint_assets, remove_fingerprinted
+    };
+
+    let out_dir = std::env::temp_dir().join("didactic-fingerprint");
+    let _ = std::fs::remove_dir_all(&out_dir);
+    std::fs::create_dir_all(out_dir.join("css")).unwrap();
+    let files = [
+        (
+            "css/main.css",
+            r#"@import "theme.css"; body { color: red; }"#
+        ),
+        ("css/theme.css", "body { background: url(../img.png); }"),
+        ("img.png", "png"),
+        ("favicon.ico", "ico")
+    ];
+    let mut hashes = std::collections::HashMap::new();
+    for (path, content) in files {
+        std::fs::write(out_dir.join(path), content).unwrap();
+        hashes.insert(
+            format!("/{path}"),
+            crate::build::hash_bytes(content.as_bytes())
+        );
+    }
+
+    let manifest = fingerprint_assets(&out_dir, &hashes).unwrap();
+    let img = &manifest["/img.png"];
+    assert_eq!(
+        *img,
+        format!("/img.{}.png", &hashes["/img.png"][..8]),
+        "The hash should go before the extension"
+    );
+    assert!(
+        !manifest.contains_key("/favicon.ico"),
+        "Fixed names should not be renamed"
+    );
+    assert!(
+        out_dir.join(MANIFEST_NAME).exists(),
+        "The manifest should be written"
+    );
+
+    let theme =
+        std::fs::read_to_string(out_dir.join(manifest["/css/theme.css"].trim_start_matches('/')))
+            .unwrap();
+    assert!(
+        theme.contains(img.as_str()),
+        "Stylesheets should point at renamed images"
+    );
+    let main =
+        std::fs::read_to_string(out_dir.join(manifest["/css/main.css"].trim_start_matches('/')))
+            .unwrap();
+    assert!(
+        main.contains(&manifest["/css/theme.css"]),
+        "Imported stylesheets should be renamed before the stylesheets importing them"
+    );
+
+    let html = bust_html(
+        r#"<a href="/img.png">Full size</a><a href="/favicon.ico">Icon</a>"#,
+        "/index.html",
+        &AssetUrls::Renamed(&manifest)
+    )
+    .unwrap();
+    assert!(
+        html.contains(&format!(r#"<a href="{img}">"#)),
+        "Links to assets should be renamed"
+    );
+    assert!(html.contains(r#"<a href="/favicon.ico">"#));
+
+    remove_fingerprinted(&out_dir).unwrap();
+    assert!(
+        !out_dir.join(img.trim_start_matches('/')).exists(),
+        "Renamed files should be removed"
+    );
+    assert!(
+        !out_dir.join(MANIFEST_NAME).exists(),
+        "The manifest should be removed"
+    );
+    assert!(out_dir.join("favicon.ico").exists());
+}
+
+#[test]
+fn test_asset_url() {
+    use crate::assets::AssetUrls;
+
+    let hashes = std::collections::HashMap::from([
+        ("/style.css".to_string(), "0123456789abcdef".to_string()),
+        ("/favicon.ico".to_string(), "fedcba9876543210".to_string())
+    ]);
+    let manifest = std::collections::BTreeMap::from([(
+        "/style.css".to_string(),
+        "/style.01234567.css".to_string()
+    )]);
+    let file_map = crate::file_map::FileMap::with_resolver_base(test_dir());
+    let render = |asset_urls: &AssetUrls, path: &str| {
+        let mut tera = tera::Tera::default();
+        tera.add_raw_template("page.html", &format!("{{{{ asset_url(path='{path}') }}}}"))
+            .unwrap();
+        crate::functions::register_functions(&mut tera, &hashes, asset_urls, &file_map, &[]);
+        tera.render("page.html", &tera::Context::new())
+    };
+
+    let renamed = AssetUrls::Renamed(&manifest);
+    assert_eq!(
+        render(&renamed, "/style.css").unwrap(),
+        "/style.01234567.css"
+    );
+    assert_eq!(
+        render(&renamed, "favicon.ico").unwrap(),
+        "/favicon.ico",
+        "Assets without a fingerprint should keep their path"
+    );
+    assert!(
+        render(&renamed, "/missing.png").is_err(),
+        "Unknown assets should fail"
+    );
+    assert_eq!(
+        render(&AssetUrls::Query(&hashes), "/style.css").unwrap(),
+        "/style.css?v=0123456789abcdef",
+        "Urls should not be autoescaped"
+    );
+}
+
+#[test]
+fn test_size_images() {
+    use crate::images::{image_sizes, size_images};
+
+    let out_dir = std::env::temp_dir().join("didactic-size-images");
+    let _ = std::fs::remove_dir_all(&out_dir);
+    std::fs::create_dir_all(&out_dir).unwrap();
+    image::RgbImage::new(3, 2)
+        .save(out_dir.join("photo.png"))
+        .unwrap();
+    std::fs::write(
+        out_dir.join("icon.svg"),
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 24 16"></svg>"#
+    )
+    .unwrap();
+
+    let sizes = image_sizes(&out_dir).unwrap();
+    let hashes = std::collections::HashMap::from([
+        ("/photo.png".to_string(), "0123456789abcdef".to_string()),
+        ("/icon.svg".to_string(), "fedcba9876543210".to_string())
+    ]);
+    crate::assets::fingerprint_assets(&out_dir, &hashes).unwrap();
+    assert!(!out_dir.join("photo.png").exists());
+
+    let html = size_images(
+        r#"<img src="photo.png"><img src="/icon.svg"><img src="/icon.svg" width="48">"#,
+        "/index.html",
+        &sizes
+    )
+    .unwrap();
+    assert!(
+        html.contains(
+            r#"<img src="photo.png" width="3" height="2" loading="lazy" decoding="async">"#
+        ),
+        "Images should be sized after their files were fingerprinted"
+    );
+    assert!(html.contains(r#"<img src="/icon.svg" width="24" height="16""#));
+    assert!(
+        html.contains(r#"<img src="/icon.svg" width="48" loading="lazy""#),
+        "Sizes set by hand should be kept"
+    );
+}
+
+#[test]
+fn test_process_images() {
+    use image::ImageEncoder;
+
+    use crate::images::{process_images, responsive_images};
+
+    let out_dir = std::env::temp_dir().join("didactic-process-images");
+    let cache_dir = std::env::temp_dir().join("didactic-process-images-cache");
+    let _ = std::fs::remove_dir_all(&out_dir);
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    std::fs::create_dir_all(&out_dir).unwrap();
+    image::RgbImage::new(40, 20)
+        .save(out_dir.join("photo.png"))
+        .unwrap();
+    // A big endian TIFF header with a single orientation entry, rotating the image by 90 degrees
+    let exif = [
+        b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0,
+        0, 0
+    ];
+    let file = std::fs::File::create(out_dir.join("rotated.png")).unwrap();
+    let mut encoder = image::codecs::png::PngEncoder::new(file);
+    encoder.set_exif_metadata(exif.to_vec()).unwrap();
+    encoder
+        .write_image(
+            image::RgbImage::new(40, 20).as_raw(),
+            40,
+            20,
+            image::ExtendedColorType::Rgb8
+        )
+        .unwrap();
+
+    let config: crate::config::ImageConfig =
+        toml::from_str("widths = [10, 80]\nformat = \"original\"\nsizes = \"50vw\"").unwrap();
+    let sets = process_images(&out_dir, &cache_dir, &config).unwrap();
+    let photo = &sets["/photo.png"];
+    assert_eq!((photo.width, photo.height), (40, 20));
+    assert_eq!(
+        photo.variants.iter().map(|(_, w)| *w).collect::<Vec<_>>(),
+        [10, 40],
+        "Images should never be scaled up"
+    );
+    let (small, _) = &photo.variants[0];
+    let small = out_dir.join(small.trim_start_matches('/'));
+    assert_eq!(image::image_dimensions(&small).unwrap(), (10, 5));
+    let rotated = &sets["/rotated.png"];
+    assert_eq!(
+        (rotated.width, rotated.height),
+        (20, 40),
+        "EXIF orientation should be applied"
+    );
+    assert_eq!(
+        rotated.variants.iter().map(|(_, w)| *w).collect::<Vec<_>>(),
+        [10, 20]
+    );
+
+    // Variants in the cache are copied instead of being resized again
+    let name = small.file_name().unwrap();
+    image::RgbImage::new(1, 1)
+        .save(cache_dir.join(name))
+        .unwrap();
+    process_images(&out_dir, &cache_dir, &config).unwrap();
+    assert_eq!(image::image_dimensions(&small).unwrap(), (1, 1));
+
+    let html = responsive_images(
+        r#"<img src="photo.png"><img src="/photo.png" sizes="100vw"><img src="/photo.png" srcset="/a.png 2x">"#,
+        "/index.html",
+        &sets,
+        &config.sizes
+    )
+    .unwrap();
+    let (large, _) = &photo.variants[1];
+    let srcset = format!("{} 10w, {large} 40w", photo.variants[0].0);
+    assert!(
+        html.contains(&format!(
+            r#"<img src="{large}" srcset="{srcset}" sizes="50vw" width="40" height="20">"#
+        )),
+        "{html}"
+    );
+    assert!(
+        html.contains(&format!(r#"sizes="100vw" srcset="{srcset}" width"#)),
+        "Sizes set by hand should be kept: {html}"
+    );
+    assert!(
+        html.contains(r#"<img src="/photo.png" srcset="/a.png 2x">"#),
+        "Images with a srcset should be left alone"
+    );
+}
+
+#[test]
+fn test_frame_alts() {
+    let source =
+        "#show math.equation: html.frame\n#html.frame[Figure]\n\nInline $x^2$ and\n$ sum_i i $\n";
+    let (dir, config, file_map) = project("frame-alts", "", &[("content/index.typ", source)]);
+    let engines = engines(&dir, &config, &file_map);
+    let compiler = crate::meta::PageCompiler {
+        engines: &engines,
+        file_map: &file_map,
+        last_modified: None,
+        inputs: typst::foundations::Dict::new()
+    };
+    let doc = compiler.compile(Path::new("index.typ")).unwrap();
+    let source = engines.page_source(real(&file_map, "index.typ")).unwrap();
+    let alts = crate::frames::frame_alts(&doc.root, &source);
+    assert_eq!(
+        alts,
+        [None, Some("x^2".to_string()), Some("sum_i i".to_string())],
+        "Frames outside equations should have no alt text"
+    );
+
+    let out_dir = dir.join("dist");
+    let html = typst_html::html(&doc).unwrap();
+    let html = crate::frames::extract_frames(&html, &alts, &out_dir).unwrap();
+    assert!(html.contains(r#"alt="""#));
+    assert!(html.contains(r#"alt="x^2""#));
+    assert!(html.contains(r#"alt="sum_i i""#));
+    assert!(out_dir.join("frames").is_dir());
+    crate::frames::clear_frames(&out_dir).unwrap();
+    assert!(
+        !out_dir.join("frames").exists(),
+        "Frames of the previous build should be removed"
+    );
+}
+
+#[test]
+fn test_preamble_diagnostics() {
+    let (dir, config, file_map) = project(
+        "preamble",
+        "[typst]\nmath = \"mathml\"\n",
+        &[("content/index.typ", "= Title\n\n$x^2$ and #missing\n")]
+    );
+    let engines = engines(&dir, &config, &file_map);
+    let real = real(&file_map, "index.typ");
+    let error = engines
+        .compile(real, typst::foundations::Dict::new())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("index.typ:3:12: unknown variable: missing"),
+        "Lines should be counted without the preamble, got {error}"
+    );
+
+    std::fs::write(dir.join("content/index.typ"), "= Title\n\n$x^2$\n").unwrap();
+    let doc = engines
+        .compile(real, typst::foundations::Dict::new())
+        .unwrap();
+    assert!(
+        typst_html::html(&doc)
+            .unwrap()
+            .contains("<msup><mrow><mi>x</mi></mrow><mrow><mn>2</mn></mrow></msup>"),
+        "The preamble should still convert equations"
+    );
+}
+
+#[test]
+fn test_mathml_fallback() {
+    let (dir, config, file_map) = project(
+        "mathml",
+        "[typst]\nmath = \"mathml\"\n",
+        &[(
+            "content/index.typ",
+            "$x_1^2$\n\n$attach(x, tl: a, bl: b)$\n\n$attach(x, t: p, tr: q)$\n"
+        )]
+    );
+    let doc = engines(&dir, &config, &file_map)
+        .compile(
+            real(&file_map, "index.typ"),
+            typst::foundations::Dict::new()
+        )
+        .unwrap();
+    let html = typst_html::html(&doc).unwrap();
+    assert!(html.contains(
+        "<msubsup><mrow><mi>x</mi></mrow><mrow><mn>1</mn></mrow><mrow><mn>2</mn></mrow></msubsup>"
+    ));
+    assert_eq!(
+        html.matches("typst-frame").count(),
+        2,
+        "Scripts before the base or stacked on one side should fall back to frames"
+    );
+}
+
+#[test]
+fn test_ignored_dotfiles() {
+    let dir = project_dir(
+        "ignored-dotfiles",
+        "[build]\nignore = [\"!.nojekyll\"]\n",
+        &[
+            (".didacticignore", "*.psd\n!.htaccess\n"),
+            ("templates/index.html", "{{ page.title }}"),
+            ("content/index.typ", "Home\n"),
+            ("static/.htaccess", ""),
+            ("static/.nojekyll", ""),
+            ("static/.env", ""),
+            ("static/cover.psd", "")
+        ]
+    );
+    crate::build::run_build(&dir, false, "release", &[]).unwrap();
+    let dist = dir.join("dist");
+    assert!(
+        dist.join(".htaccess").exists(),
+        "Whitelisted dotfiles should be copied"
+    );
+    assert!(dist.join(".nojekyll").exists());
+    assert!(
+        !dist.join(".env").exists(),
+        "Other dotfiles should still be skipped"
+    );
+    assert!(!dist.join("cover.psd").exists());
+}
+
+#[test]
+fn test_minify_exclude() {
+    let out_dir = std::env::temp_dir().join("didactic-minify-exclude");
+    let _ = std::fs::remove_dir_all(&out_dir);
+    std::fs::create_dir_all(out_dir.join("vendor")).unwrap();
+    let css = "body {\n    color: red;\n}\n";
+    std::fs::write(out_dir.join("style.css"), css).unwrap();
+    std::fs::write(out_dir.join("vendor/lib.css"), css).unwrap();
+
+    let config: crate::config::MinifyConfig =
+        toml::from_str(r#"exclude = ["vendor/**", "legacy.html"]"#).unwrap();
+    let minifier = crate::minify::Minifier::new(&config).unwrap();
+    assert!(!minifier.allows(std::path::Path::new("legacy.html")));
+    assert!(minifier.allows(std::path::Path::new("notes/legacy.html")));
+
+    crate::minify::minify_assets(&out_dir, &minifier).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(out_dir.join("style.css")).unwrap(),
+        "body{color:red}"
+    );
+    assert_eq!(
+        std::fs::read_to_string(out_dir.join("vendor/lib.css")).unwrap(),
+        css,
+        "Excluded assets should be left alone"
+    );
+}
+
+#[test]
+fn test_compress_output() {
+    let out_dir = std::env::temp_dir().join("didactic-compress");
+    let _ = std::fs::remove_dir_all(&out_dir);
+    std::fs::create_dir_all(&out_dir).unwrap();
+    std::fs::write(out_dir.join("index.html"), "<p>didactic</p>".repeat(100)).unwrap();
+    std::fs::write(out_dir.join("small.css"), "a{}").unwrap();
+    std::fs::write(out_dir.join("vendor.js"), "let didactic = 1;".repeat(100)).unwrap();
+    std::fs::write(out_dir.join("vendor.js.gz"), "shipped").unwrap();
+    let manifest = out_dir.join("cache/compressed.json");
+
+    crate::compress::compress_output(&out_dir, &manifest, None).unwrap();
+    assert!(
+        out_dir.join("vendor.js.gz").exists(),
+        "Shipped siblings should be kept when compression was never configured"
+    );
+
+    let mut config: crate::config::CompressConfig = toml::from_str("min_size = 64").unwrap();
+    crate::compress::compress_output(&out_dir, &manifest, Some(&config)).unwrap();
+    assert!(out_dir.join("index.html.gz").exists());
+    assert!(out_dir.join("index.html.br").exists());
+    assert!(
+        !out_dir.join("small.css.gz").exists(),
+        "Files under min_size should not be compressed"
+    );
+    assert_eq!(
+        std::fs::read_to_string(out_dir.join("vendor.js.gz")).unwrap(),
+        "shipped",
+        "Shipped siblings should not be overwritten"
+    );
+    assert!(out_dir.join("vendor.js.br").exists());
+
+    config.gzip = false;
+    crate::compress::compress_output(&out_dir, &manifest, Some(&config)).unwrap();
+    assert!(
+        !out_dir.join("index.html.gz").exists(),
+        "Disabled encodings should be removed"
+    );
+    assert!(out_dir.join("index.html.br").exists());
+
+    crate::compress::compress_output(&out_dir, &manifest, None).unwrap();
+    assert!(
+        !out_dir.join("index.html.br").exists(),
+        "Siblings should be removed without a [compress] table"
+    );
+    assert!(!out_dir.join("vendor.js.br").exists());
+    assert!(out_dir.join("index.html").exists());
+    assert!(out_dir.join("vendor.js.gz").exists());
+    assert!(!manifest.exists());
+}
+
+#[test]
+fn test_link_collision() {
+    let links = "[[links]]\nslug = \"notes\"\npath = \"extra\"\n";
+    let files = [("content/notes/a.typ", "A"), ("extra/a.typ", "B")];
+    let dir = project_dir("collision", links, &files);
+    let error = crate::build::build_file_map(&dir, &crate::build::read_config(&dir).unwrap())
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        error,
+        "./content/notes/a.typ and ./extra/a.typ both map to notes/a.typ"
+    );
+
+    let (_, _, file_map) = project("collision", &format!("{links}override = true\n"), &files);
+    assert_eq!(
+        real(&file_map, "notes/a.typ"),
+        Path::new("./extra/a.typ"),
+        "Overriding links should shadow content"
+    );
+}
+
+#[test]
+fn test_link_templates() {
+    let (dir, config, _) = project(
+        "link-templates",
+        "[[links]]\nslug = \"notes\"\npath = \"notes\"\ntemplates = \"notes/templates\"\n",
+        &[
+            (
+                "templates/base.html",
+                "<main>{% block content %}{% endblock %}</main>"
+            ),
+            (
+                "templates/index.html",
+                r#"{% extends "base.html" %}{% block content %}project{% endblock %}"#
+            ),
+            (
+                "notes/templates/index.html",
+                r#"{% extends "base.html" %}{% block content %}notes{% endblock %}"#
+            )
+        ]
+    );
+    let templates = crate::build::Templates::load(&dir, &config).unwrap();
+    let render = |logical: &str| {
+        templates
+            .for_page(Path::new(logical))
+            .render("index.html", &tera::Context::new())
+            .unwrap()
+    };
+    assert_eq!(render("index.typ"), "<main>project</main>");
+    assert_eq!(
+        render("notes/a.typ"),
+        "<main>notes</main>",
+        "Link templates should extend the project's base.html"
+    );
+
+    std::fs::write(dir.join("notes/index.typ"), "Notes").unwrap();
+    crate::build::run_build(&dir, false, "release", &[]).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("dist/notes/index.html")).unwrap(),
+        "<main>notes</main>"
+    );
+    assert!(
+        !dir.join("dist/notes/templates").exists(),
+        "A link's template directory should not be published"
+    );
+}
+
+#[test]
+fn test_nested_link_feeds() {
+    let dated = "#set document(title: \"Page\", date: datetime(year: 2024, month: 1, day: 2))\n";
+    let dir = project_dir(
+        "nested-link",
+        "[[links]]\nslug = \"docs/api\"\npath = \"api\"\nfeeds = false\ntitle = \"API\"\n",
+        &[
+            ("templates/index.html", "{{ page.title }}"),
+            ("content/index.typ", dated),
+            ("content/docs/index.typ", dated),
+            ("content/docs/guide.typ", dated),
+            ("api/index.typ", dated)
+        ]
+    );
+    crate::build::run_build(&dir, false, "release", &[]).unwrap();
+    let rss = std::fs::read_to_string(dir.join("dist/rss.xml")).unwrap();
+    assert!(rss.contains("/docs/guide.html"));
+    assert!(
+        !rss.contains("/docs/api"),
+        "Links nested under a section should be left out of feeds too"
+    );
+}
+
+#[test]
+fn test_prelude() {
+    let (dir, config, file_map) = project(
+        "prelude",
+        "[typst]\nprelude = \"content/prelude.typ\"\n",
+        &[
+            (
+                "content/prelude.typ",
+                "#let greeting = \"Hello\"\n#let rules(body) = {\n  show \"world\": \"there\"\n  body\n}\n"
+            ),
+            ("content/index.typ", "#greeting world\n")
+        ]
+    );
+    let engines = engines(&dir, &config, &file_map);
+    let real = real(&file_map, "index.typ");
+    let doc = engines
+        .compile(real, typst::foundations::Dict::new())
+        .unwrap();
+    assert!(
+        typst_html::html(&doc).unwrap().contains("Hello there"),
+        "Prelude definitions and rules should apply to pages"
+    );
+
+    std::fs::write(dir.join("content/index.typ"), "#greeting\n\n#missing\n").unwrap();
+    let error = engines
+        .compile(real, typst::foundations::Dict::new())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("index.typ:3:2: unknown variable: missing"),
+        "Lines should be counted without the prelude, got {error}"
+    );
+
+    std::fs::write(
+        dir.join("content/prelude.typ"),
+        "#let greeting = \"Hello\"\n#oops\n"
+    )
+    .unwrap();
+    let error = self::engines(&dir, &config, &file_map)
+        .compile(real, typst::foundations::Dict::new())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("content/prelude.typ:2:2: unknown variable: oops"),
+        "Errors in the prelude should point at it, got {error}"
+    );
+}
+
+#[test]
+fn test_package_order() {
+    let manifest = "[package]\nname = \"pkg\"\nversion = \"0.1.0\"\nentrypoint = \"lib.typ\"\n";
+    let (dir, config, file_map) = project(
+        "packages",
+        "[typst]\npackages = \"vendor\"\n",
+        &[
+            (
+                "content/index.typ",
+                "#import \"@local/pkg:0.1.0\": origin\n#origin\n"
+            ),
+            ("vendor/local/pkg/0.1.0/typst.toml", manifest),
+            (
+                "vendor/local/pkg/0.1.0/lib.typ",
+                "#let origin = \"vendor\"\n"
+            ),
+            ("data/typst/packages/local/pkg/0.1.0/typst.toml", manifest),
+            (
+                "data/typst/packages/local/pkg/0.1.0/lib.typ",
+                "#let origin = \"data\"\n"
+            ),
+            ("cache/typst/packages/local/pkg/0.1.0/typst.toml", manifest),
+            (
+                "cache/typst/packages/local/pkg/0.1.0/lib.typ",
+                "#let origin = \"cache\"\n"
+            )
+        ]
+    );
+    let package_roots = [
+        dir.join("data/typst/packages"),
+        dir.join("cache/typst/packages")
+    ];
+
+    let real = real(&file_map, "index.typ");
+    let origin = || {
+        let inputs = typst::foundations::Dict::new();
+        let fonts = crate::fonts::engine_fonts(&[]);
+        let doc = crate::engine::Engines::build(
+            &dir,
+            &config,
+            &file_map,
+            &fonts,
+            &inputs,
+            &package_roots
+        )
+        .unwrap()
+        .compile(real, inputs)
+        .unwrap();
+        let html = typst_html::html(&doc).unwrap();
+        ["vendor", "data", "cache"]
+            .into_iter()
+            .find(|root| html.contains(&format!("<p>{root}</p>")))
+            .unwrap()
+    };
+    assert_eq!(origin(), "vendor", "Vendored packages come first");
+    std::fs::remove_dir_all(dir.join("vendor/local")).unwrap();
+    assert_eq!(origin(), "data", "Then typst's local packages");
+    std::fs::remove_dir_all(dir.join("data/typst/packages/local")).unwrap();
+    assert_eq!(origin(), "cache", "Then the package cache");
+}
+
+#[test]
+fn test_missing_file_error() {
+    let (dir, config, file_map) = project(
+        "missing-file",
+        "",
+        &[("content/index.typ", "#import \"/content\"\n")]
+    );
+    let error = engines(&dir, &config, &file_map)
+        .compile(
+            real(&file_map, "index.typ"),
+            typst::foundations::Dict::new()
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("Is a directory"),
+        "Files outside packages should report the file system error: {error}"
+    );
+}
+
+#[test]
+fn test_typ_links() {
+    use std::path::{Path, PathBuf};
+
+    use crate::links::{normalize, resolve_typ_links};
+
+    assert_eq!(
+        normalize(Path::new("notes/./a/../b.typ")),
+        Some(PathBuf::from("notes/b.typ"))
+    );
+    assert_eq!(
+        normalize(Path::new("notes/../../b.typ")),
+        None,
+        "Paths escaping the root should be rejected"
+    );
+
+    let config = crate::build::read_config(&test_dir()).unwrap();
+    let file_map = crate::build::build_file_map(&test_dir(), &config).unwrap();
+    let logical = Path::new("notes/page.typ");
+    assert_eq!(
+        resolve_typ_links(r#"<a href="../index.typ#overview">"#, logical, &file_map).unwrap(),
+        r#"<a href="/index.html#overview">"#
+    );
+    assert_eq!(
+        resolve_typ_links(
+            r#"<a href="https://example.com/a.typ">"#,
+            logical,
+            &file_map
+        )
+        .unwrap(),
+        r#"<a href="https://example.com/a.typ">"#,
+        "External links should be left alone"
+    );
+    assert_eq!(
+        resolve_typ_links(
+            r#"<a href="missing.typ"><a href="../../index.typ">"#,
+            logical,
+            &file_map
+        )
+        .unwrap_err()
+        .to_string(),
+        "notes/page.typ links to unknown pages: missing.typ, ../../index.typ"
+    );
+}
+
+#[test]
+fn test_svg_size() {
+    use crate::images::svg_size;
+
+    assert_eq!(
+        svg_size(r#"<svg width="100px" height="50.4" viewBox="0 0 1 1">"#),
+        Some((100, 50))
+    );
+    assert_eq!(
+        svg_size(r#"<?xml version="1.0"?><svg viewBox="0,0 300 150">"#),
+        Some((300, 150)),
+        "The viewBox should be used without width and height"
+    );
+    assert_eq!(
+        svg_size(r#"<svg width="100%" height="100%">"#),
+        None,
+        "Relative sizes have no intrinsic size"
+    );
+}
+
+#[test]
+fn test_highlight_lines() {
+    use crate::highlight::{parse_lines, split_lines};
+
+    let mut lines: Vec<usize> = parse_lines("2, 4-6,x,9-").into_iter().collect();
+    lines.sort_unstable();
+    assert_eq!(lines, [2, 4, 5, 6], "Malformed parts should be skipped");
+
+    assert_eq!(
+        split_lines("<span class=\"a\">one\n<span class=\"b\">two</span>\nthree</span>\n"),
+        [
+            "<span class=\"a\">one</span>",
+            "<span class=\"a\"><span class=\"b\">two</span></span>",
+            "<span class=\"a\">three</span>"
+        ],
+        "Spans open across lines should be closed and reopened"
+    );
+}
+
+#[test]
+fn test_toml_value() {
+    use typst::foundations::{Str, Value};
+
+    let table: toml::Value = toml::from_str(
+        "name = \"notes\"\ncount = 3\nratio = 0.5\ndraft = false\ndate = 2024-01-02\n\
+         tags = [\"a\", 1]\nnested = { key = \"value\" }\n"
+    )
+    .unwrap();
+    let Value::Dict(dict) = crate::meta::toml_value(&table) else {
+        panic!("Tables should convert to dictionaries");
+    };
+    assert_eq!(dict.get("name").unwrap(), &Value::Str(Str::from("notes")));
+    assert_eq!(dict.get("count").unwrap(), &Value::Int(3));
+    assert_eq!(dict.get("ratio").unwrap(), &Value::Float(0.5));
+    assert_eq!(dict.get("draft").unwrap(), &Value::Bool(false));
+    assert_eq!(
+        dict.get("date").unwrap(),
+        &Value::Str(Str::from("2024-01-02")),
+        "Dates should become strings"
+    );
+    let Value::Array(tags) = dict.get("tags").unwrap() else {
+        panic!("Arrays should convert to arrays");
+    };
+    assert_eq!(tags.len(), 2);
+    let Value::Dict(nested) = dict.get("nested").unwrap() else {
+        panic!("Nested tables should convert to dictionaries");
+    };
+    assert_eq!(nested.get("key").unwrap(), &Value::Str(Str::from("value")));
+}
+
+#[test]
+fn test_hex_color() {
+    use crate::frames::hex_color;
+
+    assert_eq!(hex_color("#000"), Some("000000".to_string()));
+    assert_eq!(hex_color(" #FF8000 "), Some("ff8000".to_string()));
+    assert_eq!(hex_color("#ff80"), None);
+    assert_eq!(hex_color("#ggg"), None);
+    assert_eq!(hex_color("red"), None);
+}
+
+#[test]
+fn test_recolor_frames() {
+    use crate::config::FrameColors;
+    use crate::frames::recolor_frames;
+
+    let html = concat!(
+        r##"<svg class="typst-frame"><path fill="#000" stroke="#FF0000" style="opacity: 1;"></path>"##,
+        r##"</svg><svg><path fill="#000000"></path></svg>"##
+    );
+    assert_eq!(
+        recolor_frames(html, FrameColors::CurrentColor).unwrap(),
+        concat!(
+            r##"<svg class="typst-frame"><path fill="currentColor" stroke="#FF0000" "##,
+            r##"style="opacity: 1;"></path></svg><svg><path fill="#000000"></path></svg>"##
+        ),
+        "Only black inside frames should follow the text color"
+    );
+    assert_eq!(
+        recolor_frames(html, FrameColors::Variables).unwrap(),
+        concat!(
+            r##"<svg class="typst-frame"><path style="opacity: 1;"##,
+            r##"fill:var(--frame-000000,currentColor);stroke:var(--frame-ff0000,#ff0000);"></path>"##,
+            r##"</svg><svg><path fill="#000000"></path></svg>"##
+        ),
+        "Colors should be read from variables, keeping the existing style"
+    );
+}
+
+#[test]
+fn test_web_fonts() {
+    use crate::fonts::{load_fonts, write_web_fonts};
+
+    let font_dir = std::env::temp_dir().join("didactic-web-fonts");
+    let out_dir = std::env::temp_dir().join("didactic-web-fonts-out");
+    let _ = std::fs::remove_dir_all(&font_dir);
+    let _ = std::fs::remove_dir_all(&out_dir);
+    std::fs::create_dir_all(&font_dir).unwrap();
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let font_dirs = [font_dir];
+    write_web_fonts(&load_fonts(&font_dirs).unwrap(), &out_dir).unwrap();
+    assert!(
+        !out_dir.join("fonts.css").exists(),
+        "fonts.css should not be written without fonts"
+    );
+
+    let data = typst_assets::fonts().next().unwrap();
+    std::fs::write(font_dirs[0].join("face.otf"), data).unwrap();
+    write_web_fonts(&load_fonts(&font_dirs).unwrap(), &out_dir).unwrap();
+    assert!(out_dir.join("fonts/face.otf").exists());
+    let css = std::fs::read_to_string(out_dir.join("fonts.css")).unwrap();
+    assert!(
+        css.contains(r#"url("/fonts/face.otf") format("opentype")"#),
+        "{css}"
+    );
 }
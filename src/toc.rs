@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use lol_html::{RewriteStrSettings, element, rewrite_str};
+use scraper::{Html, Selector};
+use serde::Serialize;
+
+const HEADINGS: &str = "h1, h2, h3, h4, h5, h6";
+
+#[derive(Serialize, Debug)]
+pub struct TocEntry {
+    /// The typst heading level, `<h2>` is level 1 since `<h1>` is reserved for the title
+    pub level: usize,
+    pub text: String,
+    pub id: String,
+    pub children: Vec<TocEntry>
+}
+
+/// Gives every heading in the page body a unique `id` and returns the nested table of contents.
+/// Ids set by typst are kept as is.
+pub fn build_toc(html: &str) -> Result<(String, Vec<TocEntry>), Box<dyn Error>> {
+    let fragment = Html::parse_fragment(html);
+    let mut used: HashSet<String> = fragment
+        .select(&Selector::parse("[id]").unwrap())
+        .filter_map(|e| e.value().id())
+        .map(str::to_string)
+        .collect();
+
+    // The id to add to each heading in document order, `None` where typst already set one
+    let mut new_ids = Vec::new();
+    let mut toc = Vec::new();
+    for heading in fragment.select(&Selector::parse(HEADINGS).unwrap()) {
+        let level: usize = heading.value().name()[1..].parse()?;
+        let text = heading.text().collect::<String>().trim().to_string();
+        let id = if let Some(existing) = heading.value().id() {
+            new_ids.push(None);
+            existing.to_string()
+        } else {
+            let id = unique_id(&slugify(&text), &mut used);
+            new_ids.push(Some(id.clone()));
+            id
+        };
+
+        if level > 1 {
+            insert_entry(
+                &mut toc,
+                TocEntry {
+                    level: level - 1,
+                    text,
+                    id,
+                    children: vec![]
+                }
+            );
+        }
+    }
+
+    let mut new_ids = new_ids.into_iter();
+    let html = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!(HEADINGS, |el| {
+                if let Some(Some(id)) = new_ids.next() {
+                    el.set_attribute("id", &id)?;
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        }
+    )?;
+    Ok((html, toc))
+}
+
+fn insert_entry(siblings: &mut Vec<TocEntry>, entry: TocEntry) {
+    match siblings.last_mut() {
+        Some(last) if last.level < entry.level => insert_entry(&mut last.children, entry),
+        _ => siblings.push(entry)
+    }
+}
+
+pub fn slugify(text: &str) -> String {
+    let slug = text
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug
+    }
+}
+
+fn unique_id(base: &str, used: &mut HashSet<String>) -> String {
+    let mut id = base.to_string();
+    let mut n = 1;
+    while used.contains(&id) {
+        id = format!("{base}-{n}");
+        n += 1;
+    }
+    used.insert(id.clone());
+    id
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+use log::{debug, warn};
+
+use crate::meta::{MONTHS, WEEKDAYS};
+
+/// Looks up when a source file was last changed, preferring git history and falling back to the
+/// file modification time outside of a repository.
+#[derive(Debug)]
+pub struct LastModified {
+    root: PathBuf,
+    in_repo: bool
+}
+
+impl LastModified {
+    pub fn new(root: &Path) -> Self {
+        let in_repo = Command::new("git")
+            .args(["rev-parse", "--is-inside-work-tree"])
+            .current_dir(root)
+            .output()
+            .is_ok_and(|o| o.status.success());
+        if !in_repo {
+            warn!(
+                "{} is not inside a git repository, using file modification times",
+                root.display()
+            );
+        }
+        Self {
+            root: root.to_path_buf(),
+            in_repo
+        }
+    }
+
+    /// Takes a real path as stored in the `FileMap` and returns seconds since the epoch.
+    pub fn get(&self, real: &Path) -> Option<u64> {
+        if self.in_repo {
+            if let Some(secs) = self.read_git(real) {
+                return Some(secs);
+            }
+            debug!("{} has no git history, using mtime", real.display());
+        }
+        self.read_mtime(real)
+    }
+
+    fn read_git(&self, real: &Path) -> Option<u64> {
+        let output = Command::new("git")
+            .args(["log", "-1", "--format=%ct", "--"])
+            .arg(real)
+            .current_dir(&self.root)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8(output.stdout).ok()?.trim().parse().ok()
+    }
+
+    fn read_mtime(&self, real: &Path) -> Option<u64> {
+        let modified = fs::metadata(self.root.join(real)).ok()?.modified().ok()?;
+        Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+    }
+}
+
+/// Formats seconds since the epoch as an RFC 2822 date, as used by RSS and `page.updated`.
+pub fn format_rfc2822(secs: u64) -> String {
+    let (year, month, day) = civil_from_days(secs / 86400);
+    let rem = secs % 86400;
+    // 1970-01-01 was a Thursday
+    let weekday = (secs / 86400 + 3) % 7;
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} +0000",
+        WEEKDAYS[weekday as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Formats seconds since the epoch as an RFC 3339 date, as used by the sitemap and Atom.
+pub fn format_rfc3339(secs: u64) -> String {
+    let (year, month, day) = civil_from_days(secs / 86400);
+    let rem = secs % 86400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Seconds since the epoch at midnight of a date, `None` before 1970.
+pub fn epoch_secs(year: i64, month: u64, day: u64) -> Option<u64> {
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = u64::try_from(year - era * 400).ok()?;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + i64::try_from(doe).ok()? - 719_468;
+    u64::try_from(days).ok().map(|d| d * 86400)
+}
+
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
@@ -3,3 +3,7 @@
 #title()
 
 This is a test index page.
+
+= Overview of _the_ site <overview>
+
+A labelled heading.